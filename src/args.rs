@@ -0,0 +1,62 @@
+//! Command line options for debugging and testing content e.g.
+//!
+//! ```text
+//! tunnelcast --seed 42 --cards cards.toml
+//! ```
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Options {
+    /// Seed for the game's rng so a run can be replayed
+    pub seed: Option<u64>,
+    /// TOML file to load the card collection from instead of the
+    /// built in cards
+    pub cards: Option<PathBuf>,
+}
+
+/// Parse the arguments after the program name. Anything not given
+/// uses the default.
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("Missing value for {}", arg));
+        match arg.as_str() {
+            "--seed" => {
+                let seed = value()?;
+                let seed = seed.parse().map_err(|_| format!("Invalid seed {}", seed))?;
+                options.seed = Some(seed);
+            }
+            "--cards" => options.cards = Some(PathBuf::from(value()?)),
+            _ => return Err(format!("Unknown argument {}", arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+#[cfg(test)]
+mod test_args {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse(&[]), Ok(Options::default()));
+        assert_eq!(
+            parse(&["--seed", "42", "--cards", "cards.toml"]),
+            Ok(Options {
+                seed: Some(42),
+                cards: Some(PathBuf::from("cards.toml")),
+            })
+        );
+
+        assert_eq!(parse(&["--seed"]), Err(String::from("Missing value for --seed")));
+        assert_eq!(parse(&["--seed", "-1"]), Err(String::from("Invalid seed -1")));
+        assert_eq!(parse(&["--fast"]), Err(String::from("Unknown argument --fast")));
+    }
+}
@@ -1,57 +1,294 @@
 #![allow(dead_code)]
 
 use std::cmp::{Eq, PartialEq};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::fs;
 use std::hash::Hash;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use lazy_static::lazy_static;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+lazy_static! {
+    /// Card IDs and other names created at runtime
+    static ref INTERNED: Mutex<HashSet<&'static str>> = Mutex::new(HashSet::new());
+}
+
+/// A `&'static str` for `name` that's only allocated the first time
+/// the name is seen
+fn intern(name: &str) -> &'static str {
+    let mut names = INTERNED.lock().expect("Name registry poisoned");
+    match names.get(name) {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static str = Box::leak(name.to_string().into_boxed_str());
+            names.insert(interned);
+            interned
+        }
+    }
+}
+
+/// Identifies a card definition by an interned name so cards can be
+/// defined at runtime without adding to an enum
 #[derive(Debug, Eq, PartialEq, Hash, Copy, Clone)]
-pub enum CardId {
-    Shields,
-    Phasers,
+pub struct CardId(&'static str);
+
+impl CardId {
+    pub const SHIELDS: CardId = CardId("shields");
+    pub const PHASERS: CardId = CardId("phasers");
+    pub const AUTO_SHIELDS: CardId = CardId("auto_shields");
+
+    /// Get the ID for the card named `name`, interning the name if
+    /// it hasn't been seen before
+    pub fn new(name: &str) -> Self {
+        CardId(intern(name))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl Serialize for CardId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for CardId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Ok(CardId::new(&name))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GameError {
+    EntityNotFound(EntityId),
+    CardNotFound(CardId),
+    /// No card at the index in the hand
+    CardNotInHand(usize),
+    /// The card can't be played against the entity
+    InvalidTarget(EntityId),
+    /// The player can't pay a cost of the card other than energy
+    CannotPayCost(CardId),
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::EntityNotFound(id) => write!(f, "Could not find entity with ID {}", id),
+            GameError::CardNotFound(id) => write!(f, "Could not find card with ID {:?}", id),
+            GameError::CardNotInHand(idx) => write!(f, "No card at index {} in the hand", idx),
+            GameError::InvalidTarget(id) => write!(f, "Entity {} is not a valid target", id),
+            GameError::CannotPayCost(id) => write!(f, "Can't pay the cost of card {:?}", id),
+        }
+    }
 }
 
-#[derive(Debug)]
+impl Error for GameError {}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Action {
-    Await,
     Draw,
+    /// Play the card at the index in the hand against the target.
+    /// The target is ignored for cards that hit every enemy.
     PlayCard(EntityId, i32),
     BeginTurn,
     EndTurn,
+    /// Reveal the top cards of the draw pile
+    Scry(usize),
+    /// Discard a revealed card by its position from the top of the
+    /// draw pile
+    ScryDiscard(usize),
+    /// Stop revealing cards leaving the rest on the draw pile
+    EndScry,
+    /// Discard the card at the index in the hand
+    DiscardFromHand(usize),
+    /// Discard this many cards from the hand at random
+    DiscardRandom(u32),
+    /// Return a card to the hand by its position from the top of the
+    /// discard pile
+    SalvageCard(usize),
+    /// Repair the player's hull while resting between combats
+    RestHeal,
+    /// Upgrade the card at the index in the master deck
+    UpgradeCard(usize),
+    /// Remove the card at the index in the master deck for the rest
+    /// of the run
+    RemoveCard(usize),
+    /// Give up the run which loses the combat
+    Concede,
+    /// Revert the last card played this turn
+    UndoCard,
 }
 
-#[derive(Debug, Hash, Eq, PartialEq, Clone, Copy)]
+/// Attributes are displayed in the order the variants are declared
+#[derive(Debug, Hash, Eq, PartialEq, Ord, PartialOrd, Clone, Copy)]
 pub enum Attribute {
     Shields,
+    /// The most shields that can be regenerated
+    MaxShields,
+    /// Absorbs damage before shields but is lost at the start of the
+    /// entity's turn
+    Block,
     Hull,
+    /// The most hull the entity can have
+    MaxHull,
+    /// Deals this much damage back to anything that damages the
+    /// entity
+    DeflectorField,
+    /// Added to the damage the entity deals
+    Strength,
+    /// Added to the shields the entity gains
+    Dexterity,
+    /// Percent chance to dodge damage from another entity
+    Evasion,
+}
+
+impl Attribute {
+    pub const ALL: [Attribute; 9] = [
+        Attribute::Shields,
+        Attribute::MaxShields,
+        Attribute::Block,
+        Attribute::Hull,
+        Attribute::MaxHull,
+        Attribute::DeflectorField,
+        Attribute::Strength,
+        Attribute::Dexterity,
+        Attribute::Evasion,
+    ];
+
+    /// What the attribute does for players new to the game
+    pub fn description(&self) -> &str {
+        match self {
+            Attribute::Shields => "Absorbs damage before it reaches the hull",
+            Attribute::MaxShields => "The most shields that regenerate each turn",
+            Attribute::Block => "Absorbs damage before shields until your next turn",
+            Attribute::Hull => "Destroyed when this reaches zero",
+            Attribute::MaxHull => "The most hull that can be repaired",
+            Attribute::DeflectorField => "Deals damage back to attackers",
+            Attribute::Strength => "Added to the damage dealt by cards",
+            Attribute::Dexterity => "Added to the shields gained from cards",
+            Attribute::Evasion => "Percent chance to dodge an attack",
+        }
+    }
+
+    /// The lowest value the attribute can drop to or `None` if it can
+    /// go negative
+    pub fn floor(&self) -> Option<i32> {
+        match self {
+            Attribute::Shields
+            | Attribute::MaxShields
+            | Attribute::Block
+            | Attribute::MaxHull
+            | Attribute::DeflectorField
+            | Attribute::Evasion => Some(0),
+            // Hull is left unclamped so dropping to zero or below
+            // destroys the entity
+            Attribute::Hull => None,
+            // Negative strength and dexterity weaken cards
+            Attribute::Strength | Attribute::Dexterity => None,
+        }
+    }
+
+    /// Whether the attribute carries over to the next combat rather
+    /// than being a status that only lasts for this one
+    pub fn persists(&self) -> bool {
+        match self {
+            Attribute::Shields | Attribute::MaxShields | Attribute::Hull | Attribute::MaxHull => {
+                true
+            }
+            Attribute::Block
+            | Attribute::DeflectorField
+            | Attribute::Strength
+            | Attribute::Dexterity
+            | Attribute::Evasion => false,
+        }
+    }
+}
+
+impl fmt::Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Attribute::Shields => "Shields",
+            Attribute::MaxShields => "Max Shields",
+            Attribute::Block => "Block",
+            Attribute::Hull => "Hull",
+            Attribute::MaxHull => "Max Hull",
+            Attribute::DeflectorField => "Deflector Field",
+            Attribute::Strength => "Strength",
+            Attribute::Dexterity => "Dexterity",
+            Attribute::Evasion => "Evasion",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 pub type EntityId = u32;
 
+/// Which side of the combat an entity fights on
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Team {
+    Player,
+    Enemy,
+}
+
 pub trait Entity: std::fmt::Debug {
-    fn get_name(&self) -> &String;
+    fn name(&self) -> &str;
+    /// Copy the entity so the game can be cloned
+    fn clone_box(&self) -> Box<dyn Entity>;
     fn get_state(&self) -> &State;
     fn get_state_mut(&mut self) -> &mut State;
+    fn get_team(&self) -> Team;
+
+    /// Advance the entity's AI and return what it does this turn
+    fn next_intent(&mut self, _rng: &mut StdRng) -> Option<EnemyIntent> {
+        None
+    }
+
+    /// What the entity will do next turn without advancing its AI or
+    /// `None` if it can't be known ahead of time
+    fn peek_intent(&self) -> Option<EnemyIntent> {
+        None
+    }
+
+    /// ASCII art drawn for the entity or `None` to use the fallback
+    fn art(&self) -> Option<&'static str> {
+        None
+    }
 }
 
-fn gen_id() -> EntityId {
-    rand::random::<u32>()
+impl Clone for Box<dyn Entity> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
 }
 
 // For now, combining entities with state for simplicity.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Player {
     pub state: State,
     pub name: String,
 }
 
 impl Entity for Player {
-    fn get_name(&self) -> &String {
+    fn name(&self) -> &str {
         &self.name
     }
 
+    fn clone_box(&self) -> Box<dyn Entity> {
+        Box::new(self.clone())
+    }
+
     fn get_state(&self) -> &State {
         &self.state
     }
@@ -59,18 +296,165 @@ impl Entity for Player {
     fn get_state_mut(&mut self) -> &mut State {
         &mut self.state
     }
+
+    fn get_team(&self) -> Team {
+        Team::Player
+    }
+}
+
+/// What an enemy does on its turn
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "amount")]
+pub enum EnemyIntent {
+    Attack(i32),
+    /// Raise the enemy's own shields
+    Defend(i32),
+    /// Attack for `base` plus `per_turn` for every turn of the combat
+    /// so far
+    Escalate { base: i32, per_turn: i32 },
+}
+
+impl EnemyIntent {
+    /// The intent with escalating attacks worked out for the turn
+    pub fn for_turn(self, turn: u32) -> EnemyIntent {
+        match self {
+            EnemyIntent::Escalate { base, per_turn } => {
+                EnemyIntent::Attack(base + per_turn * turn as i32)
+            }
+            other => other,
+        }
+    }
+}
+
+/// Something an enemy does on its turn. Abilities are resolved
+/// directly against the game rather than through the player's deck.
+pub trait EnemyAbility: std::fmt::Debug {
+    fn name(&self) -> &str;
+    fn resolve(&self, game: &mut GameState, enemy_id: EntityId);
+    /// Copy the ability so the game can be cloned
+    fn clone_box(&self) -> Box<dyn EnemyAbility>;
+}
+
+impl Clone for Box<dyn EnemyAbility> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl EnemyAbility for EnemyIntent {
+    fn name(&self) -> &str {
+        match self {
+            EnemyIntent::Attack(_) | EnemyIntent::Escalate { .. } => "Attack",
+            EnemyIntent::Defend(_) => "Defend",
+        }
+    }
+
+    fn resolve(&self, game: &mut GameState, enemy_id: EntityId) {
+        match self {
+            EnemyIntent::Attack(amount) => {
+                if let Some(player) = game.entity_state.get(&game.player) {
+                    let state_change = damage(player.get_state(), *amount);
+                    game.apply_effect(enemy_id, (game.player, state_change));
+                }
+            }
+            EnemyIntent::Defend(amount) => {
+                let mut state_change = State::new();
+                state_change.insert(Attribute::Shields, *amount);
+                game.apply_effect(enemy_id, (enemy_id, state_change));
+            }
+            EnemyIntent::Escalate { .. } => self.for_turn(game.turn).resolve(game, enemy_id),
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn EnemyAbility> {
+        Box::new(*self)
+    }
+}
+
+/// Strips the player's shields every turn on top of the enemy's
+/// intent
+#[derive(Debug, Clone)]
+pub struct Barrage {
+    pub amount: i32,
+}
+
+impl EnemyAbility for Barrage {
+    fn name(&self) -> &str {
+        "Barrage"
+    }
+
+    fn resolve(&self, game: &mut GameState, enemy_id: EntityId) {
+        let mut state_change = State::new();
+        state_change.insert(Attribute::Shields, -self.amount);
+        game.apply_effect(enemy_id, (game.player, state_change));
+    }
+
+    fn clone_box(&self) -> Box<dyn EnemyAbility> {
+        Box::new(self.clone())
+    }
+}
+
+/// How an enemy picks its intent each turn
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "intents")]
+pub enum EnemyAi {
+    /// Cycle through the intents in order
+    Cycle(Vec<EnemyIntent>),
+    /// Pick an intent at random in proportion to its weight
+    #[serde(with = "weighted_intents")]
+    Weighted(Vec<(EnemyIntent, u32)>),
+}
+
+/// Writes weighted intents as tables since TOML arrays can't mix an
+/// intent with its weight
+mod weighted_intents {
+    use super::EnemyIntent;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct WeightedIntent {
+        intent: EnemyIntent,
+        weight: u32,
+    }
+
+    pub fn serialize<S: Serializer>(
+        table: &[(EnemyIntent, u32)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            table
+                .iter()
+                .map(|&(intent, weight)| WeightedIntent { intent, weight }),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(EnemyIntent, u32)>, D::Error> {
+        let table = Vec::<WeightedIntent>::deserialize(deserializer)?;
+        Ok(table.into_iter().map(|w| (w.intent, w.weight)).collect())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Enemy {
     pub name: String,
     pub state: State,
+    pub team: Team,
+    pub ai: EnemyAi,
+    /// Number of intents picked so far
+    pub ai_step: usize,
+    pub art: Option<&'static str>,
 }
 impl Entity for Enemy {
-    fn get_name(&self) -> &String {
+    fn name(&self) -> &str {
         &self.name
     }
 
+    fn clone_box(&self) -> Box<dyn Entity> {
+        Box::new(self.clone())
+    }
+
     fn get_state(&self) -> &State {
         &self.state
     }
@@ -78,350 +462,5748 @@ impl Entity for Enemy {
     fn get_state_mut(&mut self) -> &mut State {
         &mut self.state
     }
+
+    fn get_team(&self) -> Team {
+        self.team
+    }
+
+    fn next_intent(&mut self, rng: &mut StdRng) -> Option<EnemyIntent> {
+        let intent = match &self.ai {
+            EnemyAi::Cycle(intents) if intents.is_empty() => None,
+            EnemyAi::Cycle(intents) => Some(intents[self.ai_step % intents.len()]),
+            EnemyAi::Weighted(table) => table
+                .choose_weighted(rng, |(_, weight)| *weight)
+                .ok()
+                .map(|(intent, _)| *intent),
+        };
+        self.ai_step += 1;
+        intent
+    }
+
+    fn peek_intent(&self) -> Option<EnemyIntent> {
+        match &self.ai {
+            EnemyAi::Cycle(intents) if !intents.is_empty() => {
+                Some(intents[self.ai_step % intents.len()])
+            }
+            // Nothing planned or picked at random when the turn comes
+            _ => None,
+        }
+    }
+
+    fn art(&self) -> Option<&'static str> {
+        self.art
+    }
+}
+
+/// The starting stats and behavior of an enemy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnemyTemplate {
+    pub name: &'static str,
+    pub hull: i32,
+    pub shields: i32,
+    pub ai: EnemyAi,
+    /// ASCII art drawn in the enemy panel
+    pub art: Option<&'static str>,
+}
+
+/// An `EnemyTemplate` read from a content file before its strings are
+/// interned
+#[derive(Deserialize)]
+struct OwnedEnemyTemplate {
+    name: String,
+    hull: i32,
+    shields: i32,
+    ai: EnemyAi,
+    #[serde(default)]
+    art: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for EnemyTemplate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let template = OwnedEnemyTemplate::deserialize(deserializer)?;
+        Ok(EnemyTemplate {
+            name: intern(&template.name),
+            hull: template.hull,
+            shields: template.shields,
+            ai: template.ai,
+            art: template.art.as_deref().map(intern),
+        })
+    }
+}
+
+impl EnemyTemplate {
+    /// The template with its stats and attacks scaled for the
+    /// difficulty
+    pub fn scaled(&self, difficulty: Difficulty) -> EnemyTemplate {
+        let scale_intent = |intent: &EnemyIntent| match intent {
+            EnemyIntent::Attack(amount) => EnemyIntent::Attack(difficulty.scale(*amount)),
+            EnemyIntent::Escalate { base, per_turn } => EnemyIntent::Escalate {
+                base: difficulty.scale(*base),
+                per_turn: *per_turn,
+            },
+            other => *other,
+        };
+        let ai = match &self.ai {
+            EnemyAi::Cycle(intents) => EnemyAi::Cycle(intents.iter().map(scale_intent).collect()),
+            EnemyAi::Weighted(intents) => EnemyAi::Weighted(
+                intents
+                    .iter()
+                    .map(|(intent, weight)| (scale_intent(intent), *weight))
+                    .collect(),
+            ),
+        };
+
+        EnemyTemplate {
+            name: self.name,
+            hull: difficulty.scale(self.hull),
+            shields: difficulty.scale(self.shields),
+            ai,
+            art: self.art,
+        }
+    }
+}
+
+/// Add an entity on `team` built from the template returning its ID
+pub fn spawn(game: &mut GameState, template: &EnemyTemplate, team: Team) -> EntityId {
+    let mut s = State::new();
+    s.insert(Attribute::Hull, template.hull);
+    s.insert(Attribute::MaxHull, template.hull);
+    s.insert(Attribute::Shields, template.shields);
+    let enemy = Enemy {
+        name: String::from(template.name),
+        state: s,
+        team,
+        ai: template.ai.clone(),
+        ai_step: 0,
+        art: template.art,
+    };
+    game.add_entity(None, Box::new(enemy))
+}
+
+/// Add an enemy for each template scaled by the game's difficulty
+/// returning their IDs
+pub fn spawn_enemies(game: &mut GameState, templates: &[EnemyTemplate]) -> Vec<EntityId> {
+    let difficulty = game.config.difficulty;
+    let ids: Vec<EntityId> = templates
+        .iter()
+        .map(|template| spawn(game, &template.scaled(difficulty), Team::Enemy))
+        .collect();
+
+    if game.enemy.is_none() {
+        game.enemy = ids.first().copied();
+    }
+
+    ids
 }
 
 pub trait Effect: std::fmt::Debug {
-    fn calculate(&self, game: &GameState, ent_id: EntityId) -> State;
+    /// The state change the effect causes to the target when played
+    /// by `caster`
+    fn calculate(&self, game: &GameState, caster: EntityId, ent_id: EntityId) -> State;
+    /// Copy the effect so cards can be cloned
+    fn clone_box(&self) -> Box<dyn Effect>;
+
+    /// Change the game in ways a state change can't e.g. adding an
+    /// entity or moving cards between piles. Runs after the state
+    /// change has been applied with `caster` being the entity that
+    /// played the card.
+    fn resolve(&self, _game: &mut GameState, _caster: EntityId, _ent_id: EntityId) {}
+
+    /// Whether the caster can pay any cost of the effect beyond energy
+    fn can_pay(&self, _game: &GameState, _caster: EntityId) -> bool {
+        true
+    }
+
+    /// The effect as data so cards can be written to content files
+    fn descriptor(&self) -> EffectDescriptor;
+}
+
+impl Clone for Box<dyn Effect> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
 }
 
-#[derive(Debug)]
-pub struct IncreaseShields;
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncreaseShields {
+    pub amount: i32,
+}
 
 impl Effect for IncreaseShields {
-    fn calculate(&self, _game: &GameState, _ent_id: EntityId) -> State {
+    fn calculate(&self, game: &GameState, caster: EntityId, _ent_id: EntityId) -> State {
+        let dexterity = game.attribute(&caster, Attribute::Dexterity);
+
         let mut m = State::new();
-        m.insert(Attribute::Shields, 1i32);
+        m.insert(Attribute::Shields, (self.amount + dexterity).max(0));
 
         m
     }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::IncreaseShields(self.clone())
+    }
+}
+
+/// Block absorbs damage until the player's next turn
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncreaseBlock {
+    pub amount: i32,
 }
 
-#[derive(Debug)]
-pub struct DamageHull;
+impl Effect for IncreaseBlock {
+    fn calculate(&self, game: &GameState, caster: EntityId, _ent_id: EntityId) -> State {
+        let dexterity = game.attribute(&caster, Attribute::Dexterity);
 
-impl Effect for DamageHull {
-    fn calculate(&self, _game: &GameState, _ent_id: EntityId) -> State {
         let mut m = State::new();
-        m.insert(Attribute::Hull, -1i32);
+        m.insert(Attribute::Block, (self.amount + dexterity).max(0));
 
         m
     }
-}
 
-#[derive(Debug)]
-pub enum Target {
-    Player,
-    Single,
-}
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
 
-#[derive(Debug)]
-pub struct Card {
-    pub id: CardId,
-    pub name: &'static str,
-    pub effects: Vec<Box<dyn Effect>>,
-    pub target: Target,
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::IncreaseBlock(self.clone())
+    }
 }
 
-#[derive(Debug)]
-pub struct GameState {
-    pub cards: CardCollection,
-    pub draw: Vec<CardId>,
-    pub hand: Vec<CardId>,
-    pub discard: Vec<CardId>,
-    pub action: Action,
-    pub entities: Vec<EntityId>,
-    pub entity_state: HashMap<EntityId, Box<dyn Entity>>,
-    pub player: EntityId,
-    pub enemy: Option<EntityId>,
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DamageHull {
+    pub amount: i32,
 }
 
-pub type State = HashMap<Attribute, i32>;
-type StateChange = (EntityId, HashMap<Attribute, i32>);
+impl Effect for DamageHull {
+    fn calculate(&self, game: &GameState, caster: EntityId, ent_id: EntityId) -> State {
+        let strength = game.attribute(&caster, Attribute::Strength);
+        let amount = (self.amount + strength).max(0);
 
-impl GameState {
-    pub fn new(cards: CardCollection, deck: Vec<CardId>) -> GameState {
-        GameState {
-            cards,
-            draw: deck,
-            hand: vec![],
-            discard: vec![],
-            action: Action::Await,
-            entities: vec![],
-            entity_state: HashMap::new(),
-            player: gen_id(),
-            enemy: None,
+        match game.entity_state.get(&ent_id) {
+            Some(entity) => damage(entity.get_state(), amount),
+            None => State::new(),
         }
     }
 
-    pub fn add_entity(&mut self, entity_id: Option<EntityId>, entity: Box<dyn Entity>) -> EntityId {
-        let entity_id = entity_id.or_else(|| Some(gen_id())).unwrap();
-        self.entities.push(entity_id);
-        self.entity_state.insert(entity_id, entity);
-        entity_id
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
     }
 
-    fn remove_entity(&mut self, entity_id: &EntityId) {
-        let index = self
-            .entities
-            .iter()
-            .position(|x| x == entity_id)
-            .expect("EntityId not found");
-        self.entities.remove(index);
-        self.entity_state.remove(entity_id);
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::DamageHull(self.clone())
     }
+}
 
-    fn apply_effect(&mut self, state_change: StateChange) {
-        let (entity_id, state) = state_change;
-        let entity_state = self
-            .entity_state
-            .get_mut(&entity_id)
-            .expect("Failed to get entity")
-            .get_state_mut();
+/// Damage for each card already played this turn
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DamagePerCardPlayed {
+    pub amount: i32,
+}
 
-        for (k, v) in state.iter() {
-            *entity_state.entry(*k).or_insert(0) += v;
-        }
+impl Effect for DamagePerCardPlayed {
+    fn calculate(&self, game: &GameState, caster: EntityId, ent_id: EntityId) -> State {
+        let strength = game.attribute(&caster, Attribute::Strength);
+        let amount = (self.amount * game.cards_played_this_turn as i32 + strength).max(0);
 
-        // Removing entity from the game if hull drops to zero
-        if entity_state.get(&Attribute::Hull).unwrap() <= &0 {
-            self.remove_entity(&entity_id);
+        match game.entity_state.get(&ent_id) {
+            Some(entity) => damage(entity.get_state(), amount),
+            None => State::new(),
         }
     }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::DamagePerCardPlayed(self.clone())
+    }
 }
 
-/// Progress the game forward one tick
-// TODO implement a state machine for taking turns and transition
-// between stages
-// TODO maybe this should emit events that the UI layer
-// can interpret e.g. discard pile moved to draw pile
-pub fn tick(game: &mut GameState) -> &mut GameState {
-    match game.action {
-        Action::Await => (),
-        Action::Draw => {
-            // If draw pile is empty, shuffle and move discard pile
-            // into the draw pile.
-            if game.draw.iter().count() == 0 {
-                shuffle_deck(&mut game.discard);
-                game.draw.append(&mut game.discard);
-            }
+/// Damage the target at the cost of the player's own hull
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sacrifice {
+    pub hull_cost: i32,
+    pub amount: i32,
+    /// Allow playing the card even if paying the cost destroys the
+    /// player
+    pub allow_lethal: bool,
+}
 
-            if let Some(card) = game.draw.pop() {
-                game.hand.push(card);
-            };
+impl Effect for Sacrifice {
+    fn calculate(&self, game: &GameState, caster: EntityId, ent_id: EntityId) -> State {
+        let strength = game.attribute(&caster, Attribute::Strength);
+        let amount = (self.amount + strength).max(0);
+
+        match game.entity_state.get(&ent_id) {
+            Some(entity) => damage(entity.get_state(), amount),
+            None => State::new(),
         }
-        Action::PlayCard(target_ent_id, card_idx) => {
-            let card_id = &game.hand[card_idx as usize];
-            let card = &game
-                .cards
-                .get(card_id)
-                .unwrap_or_else(|| panic!("Could not find card with ID {:?}", card_id));
-
-            let mut accum = State::new();
-            for fx in &card.effects {
-                let effect = fx.calculate(&game, target_ent_id);
-
-                // Merge the effect by summing it with any existing
-                // value in the accumumulator
-                for (k, v) in effect.iter() {
-                    if let Some(val) = accum.get_mut(k) {
-                        *val += v;
-                    } else {
-                        accum.insert(*k, *v);
-                    };
-                }
-            }
+    }
 
-            // Move the card to the discard pile
-            game.discard.push(*card_id);
-            game.hand.remove(card_idx as usize);
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
 
-            // This needs to happen after discard otherwise there is a
-            // borrow error because card_id still immutably borrows
-            // GameState and apply_effect needs a mutable reference
-            game.apply_effect((target_ent_id, accum));
-        }
-        Action::BeginTurn => {
-            draw_hand(game, 4);
-        }
-        Action::EndTurn => {
-            discard_hand(game);
-        }
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::Sacrifice(self.clone())
     }
 
-    game
+    fn resolve(&self, game: &mut GameState, caster: EntityId, _ent_id: EntityId) {
+        let mut cost = State::new();
+        cost.insert(Attribute::Hull, -self.hull_cost);
+        game.apply_state_change((caster, cost));
+    }
+
+    fn can_pay(&self, game: &GameState, caster: EntityId) -> bool {
+        self.allow_lethal || game.attribute(&caster, Attribute::Hull) > self.hull_cost
+    }
 }
 
-pub fn shuffle_deck(deck: &mut Vec<CardId>) -> &mut Vec<CardId> {
-    let mut rng = thread_rng();
-    deck.shuffle(&mut rng);
-    deck
+/// Damage rolled between `min` and `max` inclusive. The roll uses the
+/// game's rng so it happens when the card resolves rather than in
+/// `calculate`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DamageRange {
+    pub min: i32,
+    pub max: i32,
 }
 
-/// Move `count` cards from the draw pile to the hand
-pub fn draw_hand(game: &mut GameState, count: i8) -> &mut GameState {
-    for _ in 0..count {
-        if let Some(card_id) = game.draw.pop() {
-            game.hand.push(card_id);
+/// Ranges with `min` above `max` are rejected when loading since
+/// there's nothing to roll
+impl<'de> Deserialize<'de> for DamageRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Range {
+            min: i32,
+            max: i32,
         }
-    }
 
-    game
-}
+        let Range { min, max } = Range::deserialize(deserializer)?;
+        if min > max {
+            return Err(serde::de::Error::custom(format!(
+                "damage range min {} is above max {}",
+                min, max
+            )));
+        }
 
-/// Move all cards from hand to the discard pile
-fn discard_hand(game: &mut GameState) -> &mut GameState {
-    // TODO handle cards that persist between turns
-    game.discard.append(&mut game.hand);
-    game
+        Ok(DamageRange { min, max })
+    }
 }
 
-#[derive(Debug)]
-pub struct CardCollection {
-    inner: HashMap<CardId, Card>,
-}
+impl Effect for DamageRange {
+    fn calculate(&self, _game: &GameState, _caster: EntityId, _ent_id: EntityId) -> State {
+        State::new()
+    }
 
-impl CardCollection {
-    pub fn new() -> Self {
-        Self {
-            inner: HashMap::new(),
-        }
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
     }
 
-    pub fn insert(&mut self, card: Card) {
-        self.inner.insert(card.id, card);
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::DamageRange(self.clone())
     }
 
-    pub fn get(&self, card_id: &CardId) -> Option<&Card> {
-        self.inner.get(card_id)
+    fn resolve(&self, game: &mut GameState, caster: EntityId, ent_id: EntityId) {
+        let strength = game.attribute(&caster, Attribute::Strength);
+        let roll = game.rng.gen_range(self.min, self.max + 1);
+        let amount = (roll + strength).max(0);
+
+        if let Some(entity) = game.entity_state.get(&ent_id) {
+            let state_change = damage(entity.get_state(), amount);
+            game.apply_effect(caster, (ent_id, state_change));
+        }
     }
 }
 
-#[cfg(test)]
-mod test_game {
-    use super::*;
+/// Deals `amount` damage `hits` times. Each hit is applied on its own
+/// so shields and deflector fields act on every hit rather than the
+/// total.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MultiHit {
+    pub hits: u32,
+    pub amount: i32,
+}
 
-    #[test]
+impl Effect for MultiHit {
+    fn calculate(&self, _game: &GameState, _caster: EntityId, _ent_id: EntityId) -> State {
+        State::new()
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::MultiHit(self.clone())
+    }
+
+    fn resolve(&self, game: &mut GameState, caster: EntityId, ent_id: EntityId) {
+        let strength = game.attribute(&caster, Attribute::Strength);
+        let amount = (self.amount + strength).max(0);
+
+        for _ in 0..self.hits {
+            // Later hits miss once the target is destroyed
+            let state_change = match game.entity_state.get(&ent_id) {
+                Some(entity) => damage(entity.get_state(), amount),
+                None => break,
+            };
+            game.apply_effect(caster, (ent_id, state_change));
+        }
+    }
+}
+
+/// Damage increased by one for each turn the card was held
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChargedDamage {
+    pub amount: i32,
+}
+
+impl Effect for ChargedDamage {
+    fn calculate(&self, game: &GameState, caster: EntityId, ent_id: EntityId) -> State {
+        let strength = game.attribute(&caster, Attribute::Strength);
+        let charge = game.playing.as_ref().map_or(0, |card| card.mods.charge);
+        let amount = (self.amount + charge + strength).max(0);
+
+        match game.entity_state.get(&ent_id) {
+            Some(entity) => damage(entity.get_state(), amount),
+            None => State::new(),
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::ChargedDamage(self.clone())
+    }
+}
+
+/// Damage equal to a percent of the target's max hull rounded down
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DamagePercentMaxHull {
+    pub percent: i32,
+}
+
+impl Effect for DamagePercentMaxHull {
+    fn calculate(&self, game: &GameState, caster: EntityId, ent_id: EntityId) -> State {
+        let strength = game.attribute(&caster, Attribute::Strength);
+        let max_hull = game.attribute(&ent_id, Attribute::MaxHull);
+        let amount = (max_hull * self.percent / 100 + strength).max(0);
+
+        match game.entity_state.get(&ent_id) {
+            Some(entity) => damage(entity.get_state(), amount),
+            None => State::new(),
+        }
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::DamagePercentMaxHull(self.clone())
+    }
+}
+
+/// Damage that goes straight to the hull ignoring shields
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DamagePiercing {
+    pub amount: i32,
+}
+
+impl Effect for DamagePiercing {
+    fn calculate(&self, game: &GameState, caster: EntityId, _ent_id: EntityId) -> State {
+        let strength = game.attribute(&caster, Attribute::Strength);
+
+        let mut m = State::new();
+        m.insert(Attribute::Hull, -(self.amount + strength).max(0));
+
+        m
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::DamagePiercing(self.clone())
+    }
+}
+
+/// Drain the target's shields without touching the hull
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReduceShields {
+    pub amount: i32,
+}
+
+impl Effect for ReduceShields {
+    fn calculate(&self, game: &GameState, _caster: EntityId, ent_id: EntityId) -> State {
+        let shields = game.attribute(&ent_id, Attribute::Shields);
+
+        let mut m = State::new();
+        m.insert(Attribute::Shields, -self.amount.clamp(0, shields.max(0)));
+
+        m
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::ReduceShields(self.clone())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncreaseStrength {
+    pub amount: i32,
+}
+
+impl Effect for IncreaseStrength {
+    fn calculate(&self, _game: &GameState, _caster: EntityId, _ent_id: EntityId) -> State {
+        let mut m = State::new();
+        m.insert(Attribute::Strength, self.amount);
+
+        m
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::IncreaseStrength(self.clone())
+    }
+}
+
+/// Reduces the cost of the next card played
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscountNextCard {
+    pub amount: u32,
+}
+
+impl Effect for DiscountNextCard {
+    fn calculate(&self, _game: &GameState, _caster: EntityId, _ent_id: EntityId) -> State {
+        State::new()
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::DiscountNextCard(self.clone())
+    }
+
+    fn resolve(&self, game: &mut GameState, _caster: EntityId, _ent_id: EntityId) {
+        game.next_card_discount += self.amount;
+    }
+}
+
+/// Makes the next card played resolve twice
+#[derive(Debug, Clone)]
+pub struct EchoNextCard;
+
+impl Effect for EchoNextCard {
+    fn calculate(&self, _game: &GameState, _caster: EntityId, _ent_id: EntityId) -> State {
+        State::new()
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::EchoNextCard
+    }
+
+    fn resolve(&self, game: &mut GameState, _caster: EntityId, _ent_id: EntityId) {
+        game.duplicate_next = true;
+    }
+}
+
+/// Lets the player return a card from the discard pile to their hand
+#[derive(Debug, Clone)]
+pub struct SalvageDiscard;
+
+impl Effect for SalvageDiscard {
+    fn calculate(&self, _game: &GameState, _caster: EntityId, _ent_id: EntityId) -> State {
+        State::new()
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::SalvageDiscard
+    }
+
+    fn resolve(&self, game: &mut GameState, _caster: EntityId, _ent_id: EntityId) {
+        // There's nothing to choose from an empty discard pile
+        game.salvaging = !game.discard.is_empty();
+    }
+}
+
+/// Draws until the hand holds `target_size` cards
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DrawUntil {
+    pub target_size: usize,
+}
+
+impl Effect for DrawUntil {
+    fn calculate(&self, _game: &GameState, _caster: EntityId, _ent_id: EntityId) -> State {
+        State::new()
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::DrawUntil(self.clone())
+    }
+
+    fn resolve(&self, game: &mut GameState, _caster: EntityId, _ent_id: EntityId) {
+        draw_until(game, self.target_size);
+    }
+}
+
+/// Adds an entity to the player's team
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Summon {
+    pub template: EnemyTemplate,
+}
+
+impl Effect for Summon {
+    fn calculate(&self, _game: &GameState, _caster: EntityId, _ent_id: EntityId) -> State {
+        State::new()
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::Summon(self.clone())
+    }
+
+    fn resolve(&self, game: &mut GameState, _caster: EntityId, _ent_id: EntityId) {
+        spawn(game, &self.template, Team::Player);
+    }
+}
+
+/// The piles cards can be added to during combat
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum CardPile {
+    Draw,
+    Discard,
+}
+
+/// Adds new copies of a card to a pile e.g. shuffling malware into
+/// the player's draw pile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AddCards {
+    pub card_id: CardId,
+    pub count: u32,
+    pub pile: CardPile,
+}
+
+impl Effect for AddCards {
+    fn calculate(&self, _game: &GameState, _caster: EntityId, _ent_id: EntityId) -> State {
+        State::new()
+    }
+
+    fn clone_box(&self) -> Box<dyn Effect> {
+        Box::new(self.clone())
+    }
+
+    fn descriptor(&self) -> EffectDescriptor {
+        EffectDescriptor::AddCards(self.clone())
+    }
+
+    fn resolve(&self, game: &mut GameState, _caster: EntityId, _ent_id: EntityId) {
+        add_cards(game, self.card_id, self.count, self.pile);
+    }
+}
+
+/// An effect and its parameters as plain data. Used to read and write
+/// cards in content files since effects are trait objects.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect")]
+pub enum EffectDescriptor {
+    IncreaseShields(IncreaseShields),
+    IncreaseBlock(IncreaseBlock),
+    DamageHull(DamageHull),
+    DamagePerCardPlayed(DamagePerCardPlayed),
+    Sacrifice(Sacrifice),
+    DamageRange(DamageRange),
+    MultiHit(MultiHit),
+    ChargedDamage(ChargedDamage),
+    DamagePiercing(DamagePiercing),
+    DamagePercentMaxHull(DamagePercentMaxHull),
+    ReduceShields(ReduceShields),
+    IncreaseStrength(IncreaseStrength),
+    DiscountNextCard(DiscountNextCard),
+    EchoNextCard,
+    SalvageDiscard,
+    DrawUntil(DrawUntil),
+    Summon(Summon),
+    AddCards(AddCards),
+}
+
+impl EffectDescriptor {
+    /// The effect described
+    pub fn build(&self) -> Box<dyn Effect> {
+        match self {
+            EffectDescriptor::IncreaseShields(e) => e.clone_box(),
+            EffectDescriptor::IncreaseBlock(e) => e.clone_box(),
+            EffectDescriptor::DamageHull(e) => e.clone_box(),
+            EffectDescriptor::DamagePerCardPlayed(e) => e.clone_box(),
+            EffectDescriptor::Sacrifice(e) => e.clone_box(),
+            EffectDescriptor::DamageRange(e) => e.clone_box(),
+            EffectDescriptor::MultiHit(e) => e.clone_box(),
+            EffectDescriptor::ChargedDamage(e) => e.clone_box(),
+            EffectDescriptor::DamagePiercing(e) => e.clone_box(),
+            EffectDescriptor::DamagePercentMaxHull(e) => e.clone_box(),
+            EffectDescriptor::ReduceShields(e) => e.clone_box(),
+            EffectDescriptor::IncreaseStrength(e) => e.clone_box(),
+            EffectDescriptor::DiscountNextCard(e) => e.clone_box(),
+            EffectDescriptor::EchoNextCard => Box::new(EchoNextCard),
+            EffectDescriptor::SalvageDiscard => Box::new(SalvageDiscard),
+            EffectDescriptor::DrawUntil(e) => e.clone_box(),
+            EffectDescriptor::Summon(e) => e.clone_box(),
+            EffectDescriptor::AddCards(e) => e.clone_box(),
+        }
+    }
+}
+
+/// How much of `amount` damage gets through the target's block and
+/// shields to its hull
+pub fn mitigated_damage(target: &State, amount: i32) -> i32 {
+    let block = target.get(&Attribute::Block).copied().unwrap_or(0).max(0);
+    let shields = target.get(&Attribute::Shields).copied().unwrap_or(0).max(0);
+    (amount - block - shields).max(0)
+}
+
+/// The state change from dealing `amount` damage to a target. Block
+/// absorbs damage first then shields before it reaches the hull.
+fn damage(target: &State, amount: i32) -> State {
+    let block = target.get(&Attribute::Block).copied().unwrap_or(0).max(0);
+    let blocked = block.min(amount);
+    let through = mitigated_damage(target, amount);
+
+    let mut m = State::new();
+    if blocked > 0 {
+        m.insert(Attribute::Block, -blocked);
+    }
+    m.insert(Attribute::Shields, -(amount - blocked - through));
+    m.insert(Attribute::Hull, -through);
+
+    m
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Target {
+    Player,
+    Single,
+    /// Every entity on the team opposing the player
+    All,
+    /// An entity on the player's team including the player
+    Ally,
+}
+
+impl Target {
+    /// Entities a card with this target can be played against. Cards
+    /// that hit every enemy are played against all of them at once.
+    pub fn valid_targets(&self, game: &GameState) -> Vec<EntityId> {
+        let candidates = match self {
+            Target::Player => vec![game.player],
+            Target::Single | Target::All => game.enemy_ids(),
+            Target::Ally => game.team_ids(Team::Player),
+        };
+
+        candidates
+            .into_iter()
+            .filter(|id| game.is_alive(id))
+            .collect()
+    }
+
+    /// The entities hit by playing a card with this target against
+    /// `target`
+    pub fn resolve(&self, game: &GameState, target: EntityId) -> Result<Vec<EntityId>, GameError> {
+        let valid_targets = self.valid_targets(game);
+        match self {
+            Target::All => Ok(valid_targets),
+            _ if valid_targets.contains(&target) => Ok(vec![target]),
+            _ => Err(GameError::InvalidTarget(target)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Card {
+    pub id: CardId,
+    pub name: &'static str,
+    pub effects: Vec<Box<dyn Effect>>,
+    pub target: Target,
+    pub tags: Vec<CardTag>,
+    /// Energy needed to play the card
+    pub cost: u32,
+}
+
+impl Card {
+    pub fn has_tag(&self, tag: CardTag) -> bool {
+        self.tags.contains(&tag)
+    }
+}
+
+/// Keywords for grouping cards that rules can refer to e.g. whenever
+/// an attack is played
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Serialize, Deserialize)]
+pub enum CardTag {
+    /// Deals damage
+    Attack,
+    /// Defends or improves the player's ship
+    Skill,
+    /// Stays in play once played instead of going to the discard pile
+    /// and its effects resolve at the start of each turn
+    Power,
+    /// Manipulates the deck or deploys other ships
+    Tech,
+    /// Stays in the hand at the end of the turn gaining a charge each
+    /// turn it's held
+    Charge,
+    /// Stays in the hand at the end of the turn
+    Retain,
+    /// Can't be played and clogs the hand e.g. malware added by
+    /// enemies
+    Unplayable,
+}
+
+pub type InstanceId = u32;
+
+/// A copy of a card in one of the piles along with state that belongs
+/// to that copy rather than every card with the same ID. The card's
+/// definition is looked up from the `CardCollection`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CardInstance {
+    /// Unique to this copy for the rest of the game
+    pub instance_id: InstanceId,
+    pub card_id: CardId,
+    pub mods: CardMods,
+}
+
+impl PartialEq<CardId> for CardInstance {
+    fn eq(&self, other: &CardId) -> bool {
+        self.card_id == *other
+    }
+}
+
+impl PartialEq<CardInstance> for CardId {
+    fn eq(&self, other: &CardInstance) -> bool {
+        *self == other.card_id
+    }
+}
+
+/// Changes to a single copy of a card
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CardMods {
+    /// Turns the card has been held in the hand. Reset when the card
+    /// leaves the hand.
+    pub charge: i32,
+    /// Upgraded cards cost one less energy to play
+    pub upgraded: bool,
+}
+
+/// Which way an attribute has to cross a threshold to fire an
+/// observer
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Crossing {
+    /// From above the threshold to at or below it
+    Falls,
+    /// From below the threshold to at or above it
+    Rises,
+}
+
+impl Crossing {
+    pub fn crossed(&self, threshold: i32, before: i32, after: i32) -> bool {
+        match self {
+            Crossing::Falls => before > threshold && after <= threshold,
+            Crossing::Rises => before < threshold && after >= threshold,
+        }
+    }
+}
+
+/// Called with the game when an observed attribute crosses its
+/// threshold
+pub type ObserverCallback = Rc<dyn Fn(&mut GameState)>;
+
+/// Watches an attribute of an entity for achievements, tutorials and
+/// scripted encounters e.g. the player's shields reaching zero
+#[derive(Clone)]
+pub struct AttributeObserver {
+    pub entity_id: EntityId,
+    pub attribute: Attribute,
+    pub threshold: i32,
+    pub crossing: Crossing,
+    pub callback: ObserverCallback,
+}
+
+impl fmt::Debug for AttributeObserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AttributeObserver")
+            .field("entity_id", &self.entity_id)
+            .field("attribute", &self.attribute)
+            .field("threshold", &self.threshold)
+            .field("crossing", &self.crossing)
+            .finish()
+    }
+}
+
+/// Bonus effects for playing `card` right after `previous` in the
+/// same turn
+#[derive(Debug, Clone)]
+pub struct Combo {
+    pub previous: CardId,
+    pub card: CardId,
+    pub bonus: Vec<Box<dyn Effect>>,
+}
+
+/// Energy the player has at the start of each turn
+const MAX_ENERGY: u32 = 3;
+
+/// Number of ticks to animate the result of a played card
+const ANIMATION_FRAMES: u8 = 3;
+
+/// Percent of the max hull repaired by resting
+const REST_HEAL_PERCENT: i32 = 30;
+
+/// The most cards the hand can hold. Cards drawn past it are burned.
+pub const MAX_HAND_SIZE: usize = 10;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TurnPhase {
+    /// Waiting for the player to choose an action
+    PlayerInput,
+    /// Showing the result of the last action for the remaining
+    /// number of ticks
+    Animating(u8),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Outcome {
+    Victory,
+    Defeat,
+    /// The player gave up the run
+    Conceded,
+}
+
+/// Events emitted by the engine for the UI layer to interpret
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum GameEvent {
+    /// The discard pile was shuffled back into the draw pile
+    DeckReshuffled,
+    CardPlayed { card_id: CardId, target: EntityId },
+    EnemyActed { enemy: EntityId, intent: EnemyIntent },
+    AbilityUsed { enemy: EntityId, ability: String },
+    /// An entity's hull dropped to zero and it was removed
+    EntityDestroyed { id: EntityId },
+    /// The player tried to draw with no cards left to draw
+    Fatigue { damage: i32 },
+    /// Block left over from the last turn was lost
+    BlockExpired { entity: EntityId, amount: i32 },
+    /// A card was drawn into a full hand and burned instead
+    OverdrawBurned { card: CardId },
+}
+
+/// A transcript of the events in a game for sharing bug reports
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CombatLog {
+    /// Seconds since the unix epoch when the log was exported
+    pub exported_at: u64,
+    pub events: Vec<GameEvent>,
+}
+
+/// How tough enemies are when they spawn
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum Difficulty {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    /// Percent enemy stats and attacks are multiplied by
+    pub fn percent(&self) -> i32 {
+        match self {
+            Difficulty::Easy => 75,
+            Difficulty::Normal => 100,
+            Difficulty::Hard => 150,
+        }
+    }
+
+    pub fn scale(&self, value: i32) -> i32 {
+        value * self.percent() / 100
+    }
+}
+
+/// Totals over the whole run shown when the game ends
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RunStats {
+    /// Hull and shields knocked off enemies by the player including
+    /// overkill
+    pub damage_dealt: i32,
+    pub cards_played: u32,
+    pub turns_taken: u32,
+    pub enemies_destroyed: u32,
+}
+
+/// What happens to the hand at the end of the turn
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum EndTurnDiscard {
+    /// Discard everything except retained and charge cards
+    #[default]
+    DiscardHand,
+    /// Keep every card in the hand for the next turn
+    KeepHand,
+}
+
+/// Optional rules that change how a game plays
+#[derive(Debug, Clone, Default)]
+pub struct GameConfig {
+    /// Drawing when the draw and discard piles are both empty damages
+    /// the player, more each time
+    pub fatigue: bool,
+    pub difficulty: Difficulty,
+    /// Shields the player regenerates at the start of each turn up to
+    /// their max shields
+    pub shield_regen: i32,
+    /// Cards drawn into a full hand are exhausted rather than
+    /// discarded
+    pub exhaust_overdraw: bool,
+    pub end_turn_discard: EndTurnDiscard,
+}
+
+/// The parts of the game a card play can change so the play can be
+/// undone. The logs are left out since they only grow and the card
+/// collection since plays don't change it.
+#[derive(Debug, Clone)]
+pub struct PlaySnapshot {
+    draw: Vec<CardInstance>,
+    hand: Vec<CardInstance>,
+    discard: Vec<CardInstance>,
+    exhaust: Vec<CardInstance>,
+    entities: Vec<EntityId>,
+    entity_state: HashMap<EntityId, Box<dyn Entity>>,
+    enemy: Option<EntityId>,
+    powers: Vec<(EntityId, CardId)>,
+    abilities: Vec<(EntityId, Box<dyn EnemyAbility>)>,
+    phase: TurnPhase,
+    scry: Vec<CardId>,
+    rng: StdRng,
+    last_played: Option<CardId>,
+    energy: u32,
+    next_card_discount: u32,
+    duplicate_next: bool,
+    salvaging: bool,
+    cards_played_this_turn: u32,
+    fatigue: u32,
+    stats: RunStats,
+    next_instance_id: InstanceId,
+    last_targeted: Option<EntityId>,
+    /// Length of the event log before the play
+    events_len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct GameState {
+    pub cards: CardCollection,
+    /// Every card the player owns for the run. Each combat's draw pile
+    /// starts as a copy of it.
+    pub master_deck: Vec<CardInstance>,
+    /// The top of the draw pile is the last element so drawing is a
+    /// `pop` off the end
+    pub draw: Vec<CardInstance>,
+    pub hand: Vec<CardInstance>,
+    pub discard: Vec<CardInstance>,
+    /// Cards removed from play for the rest of the combat
+    pub exhaust: Vec<CardInstance>,
+    /// Actions waiting to be applied by the next tick in order
+    pub actions: VecDeque<Action>,
+    pub entities: Vec<EntityId>,
+    pub entity_state: HashMap<EntityId, Box<dyn Entity>>,
+    pub player: EntityId,
+    pub enemy: Option<EntityId>,
+    /// Power cards in play along with the entity that owns them
+    pub powers: Vec<(EntityId, CardId)>,
+    /// Abilities each enemy uses every turn after its intent
+    pub abilities: Vec<(EntityId, Box<dyn EnemyAbility>)>,
+    pub events: Vec<GameEvent>,
+    pub phase: TurnPhase,
+    /// Cards revealed from the top of the draw pile, top first
+    pub scry: Vec<CardId>,
+    /// The current turn starting from 1 once the first turn begins
+    pub turn: u32,
+    /// Seed for `rng` so a game can be replayed
+    pub seed: u64,
+    /// Source of all randomness in the game e.g. shuffling and IDs
+    pub rng: StdRng,
+    /// Every action applied by `tick` in order
+    pub history: Vec<Action>,
+    pub shuffle: Shuffle,
+    pub combos: Vec<Combo>,
+    /// The last card played this turn for triggering combos
+    pub last_played: Option<CardId>,
+    /// Energy left to spend on cards this turn
+    pub energy: u32,
+    pub max_energy: u32,
+    /// Taken off the cost of the next card played
+    pub next_card_discount: u32,
+    /// The next card played resolves twice
+    pub duplicate_next: bool,
+    /// Waiting for the player to choose a card to salvage from the
+    /// discard pile
+    pub salvaging: bool,
+    pub config: GameConfig,
+    /// Cards played since the start of the turn not counting the one
+    /// being played
+    pub cards_played_this_turn: u32,
+    /// How many draws have failed with fatigue enabled which is also
+    /// the damage dealt by the last one
+    pub fatigue: u32,
+    pub stats: RunStats,
+    /// The copy of the card being played so its effects can read its
+    /// state
+    pub playing: Option<CardInstance>,
+    /// ID given to the next card instance created
+    pub next_instance_id: InstanceId,
+    /// The last enemy the player damaged which single target cards
+    /// can be replayed against
+    pub last_targeted: Option<EntityId>,
+    /// Checked after every effect is applied
+    pub observers: Vec<AttributeObserver>,
+    /// The player gave up the run
+    pub conceded: bool,
+    /// The game before the last card played this turn so the play
+    /// can be undone
+    pub undo: Option<PlaySnapshot>,
+}
+
+pub type State = HashMap<Attribute, i32>;
+type StateChange = (EntityId, HashMap<Attribute, i32>);
+
+impl GameState {
+    pub fn new(cards: CardCollection, deck: Vec<CardId>) -> GameState {
+        Self::with_seed(cards, deck, rand::random())
+    }
+
+    /// Create a game where every random outcome is determined by
+    /// `seed`
+    pub fn with_seed(cards: CardCollection, deck: Vec<CardId>, seed: u64) -> GameState {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut game = GameState {
+            cards,
+            master_deck: vec![],
+            draw: vec![],
+            hand: vec![],
+            discard: vec![],
+            exhaust: vec![],
+            actions: VecDeque::new(),
+            entities: vec![],
+            entity_state: HashMap::new(),
+            player: rng.gen(),
+            enemy: None,
+            powers: vec![],
+            abilities: vec![],
+            events: vec![],
+            phase: TurnPhase::PlayerInput,
+            scry: vec![],
+            turn: 0,
+            seed,
+            rng,
+            history: vec![],
+            shuffle: Shuffle::Random,
+            combos: vec![],
+            last_played: None,
+            energy: MAX_ENERGY,
+            max_energy: MAX_ENERGY,
+            next_card_discount: 0,
+            duplicate_next: false,
+            salvaging: false,
+            config: GameConfig::default(),
+            fatigue: 0,
+            cards_played_this_turn: 0,
+            stats: RunStats::default(),
+            playing: None,
+            next_instance_id: 0,
+            last_targeted: None,
+            observers: vec![],
+            conceded: false,
+            undo: None,
+        };
+        game.master_deck = game.instances(&deck);
+        game.draw = game.master_deck.clone();
+
+        game
+    }
+
+    /// Rebuild a game by applying `actions` to the state returned by
+    /// `setup` for the same `seed`. Animations are skipped.
+    pub fn replay<F>(seed: u64, actions: &[Action], setup: F) -> GameState
+    where
+        F: FnOnce(u64) -> GameState,
+    {
+        let mut game = setup(seed);
+        for action in actions {
+            game.actions.push_back(action.clone());
+            tick(&mut game);
+            while !game.awaiting_input() {
+                tick(&mut game);
+            }
+        }
+
+        game
+    }
+
+    /// Shuffle the draw pile using the game's RNG
+    pub fn shuffle_draw(&mut self) {
+        self.shuffle.apply(&mut self.draw, &mut self.rng);
+    }
+
+    /// IDs of every entity on `team` in the order they were added
+    pub fn team_ids(&self, team: Team) -> Vec<EntityId> {
+        self.entities
+            .iter()
+            .filter(|id| self.entity_state[id].get_team() == team)
+            .copied()
+            .collect()
+    }
+
+    pub fn enemy_ids(&self) -> Vec<EntityId> {
+        self.team_ids(Team::Enemy)
+    }
+
+    /// Enemies in the order their intents resolve when the turn ends.
+    /// Enemies act in the order they were added to the game.
+    pub fn enemy_turn_order(&self) -> Vec<EntityId> {
+        self.enemy_ids()
+            .into_iter()
+            .filter(|id| self.is_alive(id))
+            .collect()
+    }
+
+    /// The intents each enemy will pick over the next `turns` turns in
+    /// turn order. Picks are made on copies of the enemies and the rng
+    /// so the game is unchanged. Random picks only come true if
+    /// nothing else uses the rng first e.g. reshuffling the deck.
+    pub fn forecast_intents(&self, turns: u32) -> Vec<(EntityId, Vec<EnemyIntent>)> {
+        let mut rng = self.rng.clone();
+        let mut enemies: Vec<Box<dyn Entity>> = vec![];
+        let mut forecast: Vec<(EntityId, Vec<EnemyIntent>)> = vec![];
+        for enemy_id in self.enemy_turn_order() {
+            if let Some(enemy) = self.entity_state.get(&enemy_id) {
+                enemies.push(enemy.clone_box());
+                forecast.push((enemy_id, vec![]));
+            }
+        }
+
+        for turn in self.turn..self.turn + turns {
+            for (enemy, (_, intents)) in enemies.iter_mut().zip(forecast.iter_mut()) {
+                if let Some(intent) = enemy.next_intent(&mut rng) {
+                    intents.push(intent.for_turn(turn));
+                }
+            }
+        }
+
+        forecast
+    }
+
+    /// The result of the combat or `None` if it's still in progress
+    pub fn outcome(&self) -> Option<Outcome> {
+        if self.conceded {
+            Some(Outcome::Conceded)
+        } else if !self.is_alive(&self.player) {
+            Some(Outcome::Defeat)
+        } else if self.enemy_ids().is_empty() {
+            Some(Outcome::Victory)
+        } else {
+            None
+        }
+    }
+
+    pub fn entity(&self, entity_id: &EntityId) -> Result<&dyn Entity, GameError> {
+        self.entity_state
+            .get(entity_id)
+            .map(|e| e.as_ref())
+            .ok_or(GameError::EntityNotFound(*entity_id))
+    }
+
+    pub fn card(&self, card_id: &CardId) -> Result<&Card, GameError> {
+        self.cards.get(card_id).ok_or(GameError::CardNotFound(*card_id))
+    }
+
+    /// The value of the entity's attribute or zero if it doesn't have
+    /// it
+    pub fn attribute(&self, entity_id: &EntityId, attribute: Attribute) -> i32 {
+        self.entity_state
+            .get(entity_id)
+            .and_then(|e| e.get_state().get(&attribute))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// The player's attributes or `None` if the player isn't in the
+    /// game
+    pub fn player_state(&self) -> Option<&State> {
+        self.entity_state
+            .get(&self.player)
+            .map(|player| player.get_state())
+    }
+
+    /// Whether the entity is still in the game with hull remaining
+    pub fn is_alive(&self, entity_id: &EntityId) -> bool {
+        self.entity_state
+            .get(entity_id)
+            .and_then(|e| e.get_state().get(&Attribute::Hull))
+            .is_some_and(|hull| *hull > 0)
+    }
+
+    /// Write every event so far to a timestamped TOML file at `path`
+    pub fn export_log(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let log = CombatLog {
+            exported_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            events: self.events.clone(),
+        };
+        fs::write(path, toml::to_string(&log)?)?;
+
+        Ok(())
+    }
+
+    /// Check every card in the deck and piles is defined in the
+    /// collection, returning the IDs of any that aren't in the order
+    /// they're first found
+    pub fn validate(&self) -> Result<(), Vec<CardId>> {
+        let mut missing = vec![];
+        let piles = [&self.master_deck, &self.draw, &self.hand, &self.discard, &self.exhaust];
+        for card in piles.iter().flat_map(|pile| pile.iter()) {
+            if self.cards.get(&card.card_id).is_none() && !missing.contains(&card.card_id) {
+                missing.push(card.card_id);
+            }
+        }
+
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Create a new copy of the card
+    pub fn new_instance(&mut self, card_id: CardId) -> CardInstance {
+        let instance_id = self.next_instance_id;
+        self.next_instance_id += 1;
+        CardInstance {
+            instance_id,
+            card_id,
+            mods: CardMods::default(),
+        }
+    }
+
+    /// Create a new copy of each card in order
+    pub fn instances(&mut self, card_ids: &[CardId]) -> Vec<CardInstance> {
+        card_ids.iter().map(|card_id| self.new_instance(*card_id)).collect()
+    }
+
+    /// How many copies of each card are in the draw pile, hand and
+    /// discard pile combined
+    pub fn deck_composition(&self) -> HashMap<CardId, usize> {
+        let mut composition = HashMap::new();
+        for card in self.draw.iter().chain(self.hand.iter()).chain(self.discard.iter()) {
+            *composition.entry(card.card_id).or_insert(0) += 1;
+        }
+
+        composition
+    }
+
+    /// Whether the player has enough energy to play the card at the
+    /// index in the hand
+    pub fn can_afford(&self, card_idx: usize) -> bool {
+        self.card_cost(card_idx).is_some_and(|cost| cost <= self.energy)
+    }
+
+    /// Whether the card at the index in the hand can never be played
+    pub fn is_unplayable(&self, card_idx: usize) -> bool {
+        self.hand
+            .get(card_idx)
+            .and_then(|instance| self.cards.get(&instance.card_id))
+            .is_some_and(|card| card.has_tag(CardTag::Unplayable))
+    }
+
+    /// Whether the card at the index in the hand can be played now
+    pub fn can_play(&self, card_idx: usize) -> bool {
+        self.can_afford(card_idx) && !self.is_unplayable(card_idx)
+    }
+
+    /// The target to play the single target card at the index in the
+    /// hand against without asking the player
+    pub fn quick_target(&self, card_idx: usize) -> Option<EntityId> {
+        let target = self.last_targeted?;
+        let card = self.hand.get(card_idx).and_then(|c| self.cards.get(&c.card_id))?;
+        let single = matches!(card.target, Target::Single);
+        if single && card.target.valid_targets(self).contains(&target) {
+            Some(target)
+        } else {
+            None
+        }
+    }
+
+    /// Whether ending the turn now would waste energy on cards the
+    /// player could still play
+    pub fn should_confirm_end_turn(&self) -> bool {
+        self.energy > 0 && (0..self.hand.len()).any(|idx| self.can_play(idx))
+    }
+
+    /// Energy needed to play the card at the index in the hand after
+    /// any discount
+    pub fn card_cost(&self, card_idx: usize) -> Option<u32> {
+        let instance = self.hand.get(card_idx)?;
+        let upgrade = if instance.mods.upgraded { 1 } else { 0 };
+        self.cards
+            .get(&instance.card_id)
+            .map(|card| card.cost.saturating_sub(self.next_card_discount + upgrade))
+    }
+
+    /// Every action the player can take right now i.e. playing each
+    /// affordable card against each of its valid targets or ending
+    /// the turn
+    pub fn legal_actions(&self) -> Vec<Action> {
+        let mut actions = vec![];
+        for (card_idx, instance) in self.hand.iter().enumerate() {
+            if !self.can_play(card_idx) {
+                continue;
+            }
+            let card = self.cards.get(&instance.card_id).unwrap();
+            let targets = match card.target {
+                // Cards that hit every enemy only need to be played once
+                Target::All if !card.target.valid_targets(self).is_empty() => vec![self.player],
+                _ => card.target.valid_targets(self),
+            };
+            for target in targets {
+                actions.push(Action::PlayCard(target, card_idx as i32));
+            }
+        }
+        actions.push(Action::EndTurn);
+
+        actions
+    }
+
+    /// Save the state a card play can change
+    fn snapshot(&self) -> PlaySnapshot {
+        PlaySnapshot {
+            draw: self.draw.clone(),
+            hand: self.hand.clone(),
+            discard: self.discard.clone(),
+            exhaust: self.exhaust.clone(),
+            entities: self.entities.clone(),
+            entity_state: self.entity_state.clone(),
+            enemy: self.enemy,
+            powers: self.powers.clone(),
+            abilities: self.abilities.clone(),
+            phase: self.phase,
+            scry: self.scry.clone(),
+            rng: self.rng.clone(),
+            last_played: self.last_played,
+            energy: self.energy,
+            next_card_discount: self.next_card_discount,
+            duplicate_next: self.duplicate_next,
+            salvaging: self.salvaging,
+            cards_played_this_turn: self.cards_played_this_turn,
+            fatigue: self.fatigue,
+            stats: self.stats.clone(),
+            next_instance_id: self.next_instance_id,
+            last_targeted: self.last_targeted,
+            events_len: self.events.len(),
+        }
+    }
+
+    /// Put the game back how it was when the snapshot was taken
+    fn restore(&mut self, snapshot: PlaySnapshot) {
+        self.draw = snapshot.draw;
+        self.hand = snapshot.hand;
+        self.discard = snapshot.discard;
+        self.exhaust = snapshot.exhaust;
+        self.entities = snapshot.entities;
+        self.entity_state = snapshot.entity_state;
+        self.enemy = snapshot.enemy;
+        self.powers = snapshot.powers;
+        self.abilities = snapshot.abilities;
+        self.phase = snapshot.phase;
+        self.scry = snapshot.scry;
+        self.rng = snapshot.rng;
+        self.last_played = snapshot.last_played;
+        self.energy = snapshot.energy;
+        self.next_card_discount = snapshot.next_card_discount;
+        self.duplicate_next = snapshot.duplicate_next;
+        self.salvaging = snapshot.salvaging;
+        self.cards_played_this_turn = snapshot.cards_played_this_turn;
+        self.fatigue = snapshot.fatigue;
+        self.stats = snapshot.stats;
+        self.next_instance_id = snapshot.next_instance_id;
+        self.last_targeted = snapshot.last_targeted;
+        self.events.truncate(snapshot.events_len);
+    }
+
+    /// Whether the player can take an action or the game is busy
+    /// animating the last one
+    pub fn awaiting_input(&self) -> bool {
+        self.phase == TurnPhase::PlayerInput
+    }
+
+    pub fn add_entity(&mut self, entity_id: Option<EntityId>, entity: Box<dyn Entity>) -> EntityId {
+        let entity_id = entity_id.unwrap_or_else(|| self.rng.gen());
+        self.entities.push(entity_id);
+        self.entity_state.insert(entity_id, entity);
+        entity_id
+    }
+
+    pub fn remove_entity(&mut self, entity_id: &EntityId) -> Result<(), GameError> {
+        let index = self
+            .entities
+            .iter()
+            .position(|x| x == entity_id)
+            .ok_or(GameError::EntityNotFound(*entity_id))?;
+        self.entities.remove(index);
+        self.entity_state.remove(entity_id);
+
+        // Switch to the next enemy if this one was removed
+        if self.enemy == Some(*entity_id) {
+            self.enemy = self.enemy_ids().first().copied();
+        }
+
+        Ok(())
+    }
+
+    /// Apply a state change like `apply_effect` but fail if the target
+    /// isn't in the game
+    pub fn try_apply_effect(
+        &mut self,
+        source: EntityId,
+        state_change: StateChange,
+    ) -> Result<(), GameError> {
+        self.entity(&state_change.0)?;
+        self.apply_effect(source, state_change);
+
+        Ok(())
+    }
+
+    /// Call `callback` whenever the entity's attribute crosses the
+    /// threshold as the result of an effect
+    pub fn observe<F>(
+        &mut self,
+        entity_id: EntityId,
+        attribute: Attribute,
+        crossing: Crossing,
+        threshold: i32,
+        callback: F,
+    ) where
+        F: Fn(&mut GameState) + 'static,
+    {
+        self.observers.push(AttributeObserver {
+            entity_id,
+            attribute,
+            threshold,
+            crossing,
+            callback: Rc::new(callback),
+        });
+    }
+
+    /// Apply a state change caused by the `source` entity and notify
+    /// any observers of attributes it pushed across their threshold
+    fn apply_effect(&mut self, source: EntityId, state_change: StateChange) {
+        let before: Vec<i32> = self
+            .observers
+            .iter()
+            .map(|o| self.attribute(&o.entity_id, o.attribute))
+            .collect();
+
+        self.resolve_effect(source, state_change);
+
+        let fired: Vec<ObserverCallback> = self
+            .observers
+            .iter()
+            .zip(before)
+            .filter(|(o, before)| {
+                let after = self.attribute(&o.entity_id, o.attribute);
+                o.crossing.crossed(o.threshold, *before, after)
+            })
+            .map(|(o, _)| o.callback.clone())
+            .collect();
+        for callback in fired {
+            callback(self);
+        }
+    }
+
+    /// If the change damages an entity with a deflector field, the
+    /// source is damaged in return
+    fn resolve_effect(&mut self, source: EntityId, state_change: StateChange) {
+        let (entity_id, mut state) = state_change;
+
+        // A hit fully absorbed by block is still a hit
+        let damaged = [Attribute::Block, Attribute::Shields, Attribute::Hull];
+        let is_damage = damaged.iter().any(|k| state.get(k).is_some_and(|v| *v < 0));
+
+        // Only roll when the target can dodge so the seeded rng isn't
+        // advanced for everything else
+        let evasion = self.attribute(&entity_id, Attribute::Evasion);
+        if is_damage && evasion > 0 && source != entity_id && self.rng.gen_range(0, 100) < evasion {
+            state.retain(|k, v| !damaged.contains(k) || *v >= 0);
+            self.apply_state_change((entity_id, state));
+            return;
+        }
+        let deflector = self
+            .entity_state
+            .get(&entity_id)
+            .and_then(|e| e.get_state().get(&Attribute::DeflectorField))
+            .copied()
+            .unwrap_or(0);
+
+        let hits_enemy = self.entity(&entity_id).is_ok_and(|e| e.get_team() == Team::Enemy);
+        if is_damage && hits_enemy && source == self.player {
+            let dealt: i32 = [Attribute::Hull, Attribute::Shields]
+                .iter()
+                .filter_map(|k| state.get(k))
+                .filter(|v| **v < 0)
+                .sum();
+            self.stats.damage_dealt -= dealt;
+            self.last_targeted = Some(entity_id);
+        }
+
+        self.apply_state_change((entity_id, state));
+
+        // Retaliation is applied directly so it can't trigger another
+        // deflector field
+        if is_damage && deflector > 0 && source != entity_id {
+            if let Some(attacker) = self.entity_state.get(&source) {
+                let retaliation = damage(attacker.get_state(), deflector);
+                self.apply_state_change((source, retaliation));
+            }
+        }
+    }
+
+    fn apply_state_change(&mut self, state_change: StateChange) {
+        let (entity_id, state) = state_change;
+        let entity = match self.entity_state.get_mut(&entity_id) {
+            Some(entity) => entity,
+            // The entity may have been destroyed by an earlier effect
+            // in the same tick
+            None => return,
+        };
+        let team = entity.get_team();
+        let entity_state = entity.get_state_mut();
+
+        for (k, v) in state.iter() {
+            let value = entity_state.entry(*k).or_insert(0);
+            *value += v;
+            if let Some(floor) = k.floor() {
+                *value = (*value).max(floor);
+            }
+        }
+
+        // Removing entity from the game if hull drops to zero
+        if entity_state.get(&Attribute::Hull).is_some_and(|hull| *hull <= 0)
+            && self.remove_entity(&entity_id).is_ok()
+        {
+            self.on_entity_removed(entity_id, team);
+        }
+    }
+
+    /// Called after an entity is destroyed. This is the place to hand
+    /// out any rewards for destroying it.
+    fn on_entity_removed(&mut self, entity_id: EntityId, team: Team) {
+        if team == Team::Enemy {
+            self.stats.enemies_destroyed += 1;
+        }
+        if self.last_targeted == Some(entity_id) {
+            self.last_targeted = None;
+        }
+        self.events.push(GameEvent::EntityDestroyed { id: entity_id });
+    }
+}
+
+/// Builds a game with the player and enemies already in place so
+/// tests and headless runs don't have to add them by hand
+pub struct GameStateBuilder {
+    cards: CardCollection,
+    deck: Vec<CardId>,
+    player: Option<Player>,
+    enemies: Vec<EnemyTemplate>,
+    combos: Vec<Combo>,
+    config: GameConfig,
+    seed: Option<u64>,
+}
+
+impl GameStateBuilder {
+    pub fn new(cards: CardCollection, deck: Vec<CardId>) -> Self {
+        GameStateBuilder {
+            cards,
+            deck,
+            player: None,
+            enemies: vec![],
+            combos: vec![],
+            config: GameConfig::default(),
+            seed: None,
+        }
+    }
+
+    pub fn player(mut self, player: Player) -> Self {
+        self.player = Some(player);
+        self
+    }
+
+    /// Add enemies built from the templates. They're scaled by the
+    /// difficulty in the config when the game is built.
+    pub fn enemies(mut self, templates: &[EnemyTemplate]) -> Self {
+        self.enemies.extend_from_slice(templates);
+        self
+    }
+
+    pub fn combo(mut self, combo: Combo) -> Self {
+        self.combos.push(combo);
+        self
+    }
+
+    pub fn config(mut self, config: GameConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Determine every random outcome by `seed` instead of a random
+    /// seed
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn build(self) -> GameState {
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let mut game = GameState::with_seed(self.cards, self.deck, seed);
+        game.config = self.config;
+        game.combos = self.combos;
+
+        if let Some(player) = self.player {
+            game.player = game.add_entity(None, Box::new(player));
+        }
+        spawn_enemies(&mut game, &self.enemies);
+
+        game
+    }
+}
+
+/// Progress the game forward one tick
+// TODO implement a state machine for taking turns and transition
+// between stages
+pub fn tick(game: &mut GameState) -> &mut GameState {
+    // Count down any animation before handing control back to the
+    // player
+    if let TurnPhase::Animating(frames_left) = game.phase {
+        game.phase = match frames_left {
+            0 | 1 => TurnPhase::PlayerInput,
+            n => TurnPhase::Animating(n - 1),
+        };
+        return game;
+    }
+
+    // Apply every queued action including any follow ups they queue
+    while let Some(action) = game.actions.pop_front() {
+        game.history.push(action.clone());
+        apply_action(game, action);
+    }
+
+    game
+}
+
+fn apply_action(game: &mut GameState, action: Action) {
+    // Only a card played just now can be undone so anything else the
+    // player does in between takes the undo away
+    if !matches!(action, Action::PlayCard(..) | Action::UndoCard) {
+        game.undo = None;
+    }
+
+    match action {
+        Action::Draw => {
+            draw_hand(game, 1);
+        }
+        Action::PlayCard(_, card_idx) if !game.can_play(card_idx as usize) => (),
+        Action::PlayCard(target_ent_id, card_idx) => {
+            // Only the last play can be undone
+            let snapshot = game.snapshot();
+
+            // Invalid plays are ignored leaving the card in the hand so
+            // the player can choose again
+            if play_card(game, target_ent_id, card_idx as usize).is_ok() {
+                game.undo = Some(snapshot);
+            }
+        }
+        Action::BeginTurn => {
+            game.turn += 1;
+            game.stats.turns_taken += 1;
+            game.energy = game.max_energy;
+            game.cards_played_this_turn = 0;
+
+            // Block from last turn expires first so any block granted
+            // by powers lasts through this turn
+            expire_block(game, game.player);
+
+            // Resolve any powers in play before drawing a new hand the
+            // same way as playing the card. Powers are owned by the
+            // entity they affect.
+            for (owner, card_id) in game.powers.clone() {
+                resolve_card(game, owner, card_id, &[owner]);
+            }
+
+            regenerate_shields(game);
+
+            draw_hand(game, 4);
+        }
+        Action::EndTurn => {
+            game.last_played = None;
+            discard_hand(game);
+            enemy_turn(game);
+        }
+        Action::Scry(count) => {
+            game.scry = top_of_draw(game, count);
+        }
+        Action::ScryDiscard(position) => {
+            if position < game.scry.len() && position < game.draw.len() {
+                let idx = game.draw.len() - 1 - position;
+                let card = game.draw.remove(idx);
+                game.discard.push(card);
+                game.scry.remove(position);
+            }
+        }
+        Action::EndScry => {
+            game.scry.clear();
+        }
+        Action::DiscardFromHand(card_idx) => {
+            if card_idx < game.hand.len() {
+                let card = game.hand.remove(card_idx);
+                discard(game, card);
+            }
+        }
+        Action::SalvageCard(position) => {
+            if position < game.discard.len() {
+                let idx = game.discard.len() - 1 - position;
+                let card = game.discard.remove(idx);
+                game.hand.push(card);
+            }
+            game.salvaging = false;
+        }
+        Action::Concede => {
+            game.conceded = true;
+        }
+        Action::UndoCard => {
+            // The history is kept so the undo is part of any replay
+            if let Some(snapshot) = game.undo.take() {
+                game.restore(snapshot);
+            }
+        }
+        Action::RestHeal => {
+            let hull = game.attribute(&game.player, Attribute::Hull);
+            let max_hull = game.attribute(&game.player, Attribute::MaxHull);
+            let amount = (max_hull * REST_HEAL_PERCENT / 100).min(max_hull - hull).max(0);
+            let mut state_change = State::new();
+            state_change.insert(Attribute::Hull, amount);
+            game.apply_state_change((game.player, state_change));
+        }
+        Action::UpgradeCard(idx) => {
+            if let Some(card) = game.master_deck.get_mut(idx) {
+                card.mods.upgraded = true;
+            }
+        }
+        Action::RemoveCard(idx) => {
+            if idx < game.master_deck.len() {
+                game.master_deck.remove(idx);
+            }
+        }
+        Action::DiscardRandom(count) => {
+            for _ in 0..count {
+                if game.hand.is_empty() {
+                    break;
+                }
+                let card_idx = game.rng.gen_range(0, game.hand.len());
+                let card = game.hand.remove(card_idx);
+                discard(game, card);
+            }
+        }
+    }
+}
+
+/// Play the card at the index in the hand against the target
+fn play_card(
+    game: &mut GameState,
+    target_ent_id: EntityId,
+    card_idx: usize,
+) -> Result<(), GameError> {
+    let card_id = game.hand.get(card_idx).ok_or(GameError::CardNotInHand(card_idx))?.card_id;
+    let card = game.cards.get(&card_id).ok_or(GameError::CardNotFound(card_id))?;
+    let targets = card.target.resolve(game, target_ent_id)?;
+    // Cards in the hand are always played by the player
+    let caster = game.player;
+    if !card.effects.iter().all(|effect| effect.can_pay(game, caster)) {
+        return Err(GameError::CannotPayCost(card_id));
+    }
+    let cost = game.card_cost(card_idx).unwrap_or(card.cost);
+
+    let instance = game.hand.remove(card_idx);
+    game.playing = Some(instance.clone());
+    game.energy -= cost;
+    game.next_card_discount = 0;
+
+    // An echoed card resolves a second time against the same targets
+    let repeats = if game.duplicate_next { 2 } else { 1 };
+    game.duplicate_next = false;
+
+    if card.has_tag(CardTag::Power) {
+        // Powers are installed rather than resolved immediately
+        for _ in 0..repeats {
+            game.powers.push((target_ent_id, card_id));
+        }
+    } else {
+        // Move the card to the discard pile
+        discard(game, instance);
+        for _ in 0..repeats {
+            resolve_card(game, caster, card_id, &targets);
+        }
+    }
+
+    game.playing = None;
+    game.last_played = Some(card_id);
+    game.cards_played_this_turn += 1;
+    game.stats.cards_played += 1;
+    game.events.push(GameEvent::CardPlayed {
+        card_id,
+        target: target_ent_id,
+    });
+    game.phase = TurnPhase::Animating(ANIMATION_FRAMES);
+
+    Ok(())
+}
+
+/// Apply the card's effects along with any combo bonus to each target
+/// on behalf of the caster
+fn resolve_card(game: &mut GameState, caster: EntityId, card_id: CardId, targets: &[EntityId]) {
+    let card = match game.cards.get(&card_id) {
+        Some(card) => card,
+        None => return,
+    };
+    let combo = game.last_played.and_then(|previous| {
+        game.combos
+            .iter()
+            .find(|combo| combo.previous == previous && combo.card == card_id)
+    });
+    let state_changes: Vec<StateChange> = targets
+        .iter()
+        .map(|target| {
+            let mut change = calculate_effects(game, &card.effects, caster, *target);
+            if let Some(combo) = combo {
+                let bonus = calculate_effects(game, &combo.bonus, caster, *target);
+                merge_state(&mut change, &bonus);
+            }
+            (*target, change)
+        })
+        .collect();
+
+    // The effects are cloned so they can borrow the game mutably
+    // while they resolve
+    let effects = card.effects.clone();
+
+    for state_change in state_changes {
+        game.apply_effect(caster, state_change);
+    }
+
+    for target in targets {
+        for effect in effects.iter() {
+            effect.resolve(game, caster, *target);
+        }
+    }
+}
+
+/// Clean up once a combat is over. Every card goes back to the master
+/// deck and anything that only lasts for the combat is cleared, but
+/// the player's hull and shields carry over to the next one.
+pub fn end_combat(game: &mut GameState) {
+    game.draw.clear();
+    game.hand.clear();
+    game.discard.clear();
+    game.exhaust.clear();
+    game.scry.clear();
+    game.salvaging = false;
+    game.playing = None;
+
+    game.energy = 0;
+    game.next_card_discount = 0;
+    game.duplicate_next = false;
+    game.cards_played_this_turn = 0;
+    game.last_played = None;
+    game.last_targeted = None;
+    game.undo = None;
+    game.fatigue = 0;
+    game.turn = 0;
+    game.powers.clear();
+    game.abilities.clear();
+    game.actions.clear();
+    game.phase = TurnPhase::PlayerInput;
+
+    // Summoned allies only fight for the combat they were summoned in
+    for entity_id in game.entities.clone() {
+        if entity_id != game.player {
+            let _ = game.remove_entity(&entity_id);
+        }
+    }
+    if let Some(player) = game.entity_state.get_mut(&game.player) {
+        player.get_state_mut().retain(|attribute, _| attribute.persists());
+    }
+}
+
+/// Restore the player's shields by the configured amount without
+/// going over their max shields. Players without max shields don't
+/// regenerate.
+fn regenerate_shields(game: &mut GameState) {
+    let shields = game.attribute(&game.player, Attribute::Shields);
+    let max_shields = game.attribute(&game.player, Attribute::MaxShields);
+    let amount = game.config.shield_regen.min(max_shields - shields);
+    if amount > 0 {
+        let mut state_change = State::new();
+        state_change.insert(Attribute::Shields, amount);
+        game.apply_effect(game.player, (game.player, state_change));
+    }
+}
+
+/// Remove the entity's block left over from the last turn
+fn expire_block(game: &mut GameState, entity_id: EntityId) {
+    let amount = game.attribute(&entity_id, Attribute::Block);
+    if amount > 0 {
+        let mut state_change = State::new();
+        state_change.insert(Attribute::Block, -amount);
+        game.apply_state_change((entity_id, state_change));
+        game.events.push(GameEvent::BlockExpired {
+            entity: entity_id,
+            amount,
+        });
+    }
+}
+
+/// Start a new combat against the enemies with a fresh draw pile
+/// copied from the master deck
+pub fn start_combat(game: &mut GameState, templates: &[EnemyTemplate]) {
+    game.draw = game.master_deck.clone();
+    game.shuffle_draw();
+
+    spawn_enemies(game, templates);
+    game.actions.push_back(Action::BeginTurn);
+}
+
+/// A fight against a set of enemies
+#[derive(Debug, Clone)]
+pub struct Encounter {
+    pub enemies: Vec<EnemyTemplate>,
+}
+
+/// The encounters of a campaign fought one after another. The deck and
+/// the player's hull carry over from one encounter to the next.
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub encounters: Vec<Encounter>,
+    /// Index of the encounter being fought
+    pub current_encounter: usize,
+}
+
+impl Run {
+    pub fn new(encounters: Vec<Encounter>) -> Self {
+        Run {
+            encounters,
+            current_encounter: 0,
+        }
+    }
+
+    /// The encounter being fought or `None` once every encounter has
+    /// been won
+    pub fn current(&self) -> Option<&Encounter> {
+        self.encounters.get(self.current_encounter)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.current().is_none()
+    }
+
+    /// Start the combat for the current encounter
+    pub fn start_encounter(&self, game: &mut GameState) {
+        if let Some(encounter) = self.current() {
+            start_combat(game, &encounter.enemies);
+        }
+    }
+
+    /// Clean up after winning the current encounter and move on to the
+    /// next one which is returned
+    pub fn advance(&mut self, game: &mut GameState) -> Option<&Encounter> {
+        end_combat(game);
+        self.current_encounter += 1;
+        self.current()
+    }
+}
+
+/// The action autoplay takes next. The first affordable card is played
+/// against its first valid target until there's nothing left to play
+/// and the turn is ended.
+pub fn autoplay_action(game: &GameState) -> Action {
+    if game.salvaging {
+        return Action::SalvageCard(0);
+    }
+    if !game.scry.is_empty() {
+        return Action::EndScry;
+    }
+    game.legal_actions().into_iter().next().unwrap_or(Action::EndTurn)
+}
+
+/// Play out the rest of the turn with `autoplay_action` for quickly
+/// testing the whole game. Animations are skipped.
+pub fn autoplay_turn(game: &mut GameState) {
+    let mut action = autoplay_action(game);
+    loop {
+        let hand_size = game.hand.len();
+        game.actions.push_back(action.clone());
+        tick(game);
+        while !game.awaiting_input() {
+            tick(game);
+        }
+        if action == Action::EndTurn || game.outcome().is_some() {
+            break;
+        }
+
+        // A card the player can't pay for stays in the hand so end
+        // the turn rather than trying it forever
+        let stuck = matches!(action, Action::PlayCard(..)) && game.hand.len() >= hand_size;
+        action = if stuck { Action::EndTurn } else { autoplay_action(game) };
+    }
+}
+
+/// Each enemy carries out its next intent against the player
+fn enemy_turn(game: &mut GameState) {
+    for enemy_id in game.enemy_turn_order() {
+        expire_block(game, enemy_id);
+
+        // Escalating attacks are fixed for the turn when picked
+        let turn = game.turn;
+        let intent = match game.entity_state.get_mut(&enemy_id) {
+            Some(enemy) => enemy.next_intent(&mut game.rng).map(|i| i.for_turn(turn)),
+            None => None,
+        };
+
+        if let Some(intent) = intent {
+            intent.resolve(game, enemy_id);
+            game.events.push(GameEvent::EnemyActed {
+                enemy: enemy_id,
+                intent,
+            });
+        }
+
+        let abilities: Vec<Box<dyn EnemyAbility>> = game
+            .abilities
+            .iter()
+            .filter(|(owner, _)| *owner == enemy_id)
+            .map(|(_, ability)| ability.clone())
+            .collect();
+        for ability in abilities {
+            // The enemy may have been destroyed by retaliation
+            if !game.is_alive(&enemy_id) {
+                break;
+            }
+            ability.resolve(game, enemy_id);
+            game.events.push(GameEvent::AbilityUsed {
+                enemy: enemy_id,
+                ability: ability.name().to_string(),
+            });
+        }
+    }
+}
+
+/// Sum the state changes of each effect the caster applies to the
+/// target entity
+fn calculate_effects(
+    game: &GameState,
+    effects: &[Box<dyn Effect>],
+    caster: EntityId,
+    ent_id: EntityId,
+) -> State {
+    let mut accum = State::new();
+    for fx in effects {
+        let effect = fx.calculate(game, caster, ent_id);
+        merge_state(&mut accum, &effect);
+    }
+
+    accum
+}
+
+/// Merge the state change into the accumulator by summing it with
+/// any existing values
+fn merge_state(accum: &mut State, change: &State) {
+    for (k, v) in change.iter() {
+        *accum.entry(*k).or_insert(0) += v;
+    }
+}
+
+/// Calculate the state change the player playing `card_id` against
+/// the target would cause without applying it
+pub fn preview_card(
+    game: &GameState,
+    card_id: &CardId,
+    ent_id: EntityId,
+) -> Result<State, GameError> {
+    let card = game.card(card_id)?;
+
+    Ok(calculate_effects(game, &card.effects, game.player, ent_id))
+}
+
+/// The state the target would be left in if the player applied
+/// `effects` to it. Chance based outcomes like evasion aren't rolled.
+pub fn preview_apply(game: &GameState, ent_id: EntityId, effects: &[Box<dyn Effect>]) -> State {
+    let mut state = game
+        .entity_state
+        .get(&ent_id)
+        .map(|entity| entity.get_state().clone())
+        .unwrap_or_default();
+
+    for (k, v) in calculate_effects(game, effects, game.player, ent_id).iter() {
+        let value = state.entry(*k).or_insert(0);
+        *value += v;
+        if let Some(floor) = k.floor() {
+            *value = (*value).max(floor);
+        }
+    }
+
+    state
+}
+
+pub fn shuffle_deck<'a, T>(deck: &'a mut Vec<T>, rng: &mut impl Rng) -> &'a mut Vec<T> {
+    deck.shuffle(rng);
+    deck
+}
+
+/// How piles are shuffled during the game
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Shuffle {
+    Random,
+    /// Leave the cards in order for deterministic debugging
+    Identity,
+}
+
+impl Shuffle {
+    pub fn apply<T>(&self, deck: &mut Vec<T>, rng: &mut impl Rng) {
+        match self {
+            Shuffle::Random => {
+                shuffle_deck(deck, rng);
+            }
+            Shuffle::Identity => (),
+        }
+    }
+
+    /// Put a card somewhere in the deck. Identity puts it on top.
+    pub fn insert<T>(&self, deck: &mut Vec<T>, card: T, rng: &mut impl Rng) {
+        match self {
+            Shuffle::Random => {
+                let idx = rng.gen_range(0, deck.len() + 1);
+                deck.insert(idx, card);
+            }
+            Shuffle::Identity => deck.push(card),
+        }
+    }
+}
+
+/// Add `count` new copies of the card to the pile. Cards are shuffled
+/// into the draw pile and put on top of the discard pile.
+pub fn add_cards(game: &mut GameState, card_id: CardId, count: u32, pile: CardPile) {
+    for _ in 0..count {
+        let card = game.new_instance(card_id);
+        match pile {
+            CardPile::Draw => game.shuffle.insert(&mut game.draw, card, &mut game.rng),
+            CardPile::Discard => game.discard.push(card),
+        }
+    }
+}
+
+/// Take the top card of the draw pile. If the draw pile is empty,
+/// the discard pile is shuffled and becomes the new draw pile.
+fn draw_card(game: &mut GameState) -> Option<CardInstance> {
+    if game.draw.is_empty() && !game.discard.is_empty() {
+        game.shuffle.apply(&mut game.discard, &mut game.rng);
+        game.draw.append(&mut game.discard);
+        game.events.push(GameEvent::DeckReshuffled);
+    }
+
+    if game.draw.is_empty() && game.config.fatigue {
+        game.fatigue += 1;
+        let damage = game.fatigue as i32;
+        let mut state_change = State::new();
+        state_change.insert(Attribute::Hull, -damage);
+        game.apply_state_change((game.player, state_change));
+        game.events.push(GameEvent::Fatigue { damage });
+    }
+
+    game.draw.pop()
+}
+
+/// The top `count` cards of the draw pile with the top card first
+pub fn top_of_draw(game: &GameState, count: usize) -> Vec<CardId> {
+    game.draw.iter().rev().take(count).map(|card| card.card_id).collect()
+}
+
+/// Move `count` cards from the draw pile to the hand. Cards drawn
+/// once the hand holds `MAX_HAND_SIZE` are burned to the discard or
+/// exhaust pile depending on the config.
+pub fn draw_hand(game: &mut GameState, count: i8) -> &mut GameState {
+    for _ in 0..count {
+        let card = match draw_card(game) {
+            Some(card) => card,
+            None => continue,
+        };
+        if game.hand.len() < MAX_HAND_SIZE {
+            game.hand.push(card);
+            continue;
+        }
+
+        game.events.push(GameEvent::OverdrawBurned { card: card.card_id });
+        if game.config.exhaust_overdraw {
+            game.exhaust.push(card);
+        } else {
+            game.discard.push(card);
+        }
+    }
+
+    game
+}
+
+/// Draw cards until the hand holds `target_size` cards, capped at
+/// `MAX_HAND_SIZE`, or the draw and discard piles run out
+pub fn draw_until(game: &mut GameState, target_size: usize) {
+    let target_size = target_size.min(MAX_HAND_SIZE);
+    while game.hand.len() < target_size {
+        match draw_card(game) {
+            Some(card) => game.hand.push(card),
+            None => break,
+        }
+    }
+}
+
+/// Move all cards from hand to the discard pile. Retained cards stay
+/// in the hand and charge cards stay and gain a charge. Every card
+/// stays if the config keeps the hand.
+fn discard_hand(game: &mut GameState) -> &mut GameState {
+    let keep_hand = game.config.end_turn_discard == EndTurnDiscard::KeepHand;
+    let cards = &game.cards;
+    let has_tag = |instance: &CardInstance, tag: CardTag| {
+        cards
+            .get(&instance.card_id)
+            .is_some_and(|card| card.has_tag(tag))
+    };
+    let (mut kept, discarded): (Vec<CardInstance>, Vec<CardInstance>) =
+        game.hand.drain(..).partition(|instance| {
+            keep_hand || has_tag(instance, CardTag::Charge) || has_tag(instance, CardTag::Retain)
+        });
+
+    for instance in kept.iter_mut() {
+        if has_tag(instance, CardTag::Charge) {
+            instance.mods.charge += 1;
+        }
+    }
+    game.hand = kept;
+    for instance in discarded {
+        discard(game, instance);
+    }
+    game
+}
+
+/// Put a card from the hand on the discard pile
+fn discard(game: &mut GameState, mut card: CardInstance) {
+    card.mods.charge = 0;
+    game.discard.push(card);
+}
+
+#[derive(Debug, Clone)]
+pub struct CardCollection {
+    inner: HashMap<CardId, Card>,
+}
+
+impl CardCollection {
+    pub fn new() -> Self {
+        Self {
+            inner: HashMap::new(),
+        }
+    }
+
+    pub fn insert(&mut self, card: Card) {
+        self.inner.insert(card.id, card);
+    }
+
+    pub fn get(&self, card_id: &CardId) -> Option<&Card> {
+        self.inner.get(card_id)
+    }
+
+    /// IDs of every card in the collection in no particular order
+    pub fn all_ids(&self) -> Vec<CardId> {
+        self.inner.keys().copied().collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&CardId, &Card)> {
+        self.inner.iter()
+    }
+
+    /// IDs of the cards with the tag in no particular order
+    pub fn with_tag(&self, tag: CardTag) -> Vec<CardId> {
+        self.inner
+            .values()
+            .filter(|card| card.has_tag(tag))
+            .map(|card| card.id)
+            .collect()
+    }
+
+    /// Write every card to TOML sorted by ID so the output is stable
+    pub fn to_toml(&self) -> Result<String, toml::ser::Error> {
+        let mut cards: Vec<&Card> = self.inner.values().collect();
+        cards.sort_by_key(|card| card.id.as_str());
+
+        let file = CardFile {
+            cards: cards
+                .into_iter()
+                .map(|card| CardDescriptor {
+                    id: card.id,
+                    name: card.name.to_string(),
+                    target: card.target.clone(),
+                    tags: card.tags.clone(),
+                    cost: card.cost,
+                    effects: card.effects.iter().map(|e| e.descriptor()).collect(),
+                })
+                .collect(),
+        };
+
+        // Going through a `Value` puts plain values ahead of tables
+        // which TOML requires
+        toml::to_string(&toml::Value::try_from(file)?)
+    }
+
+    pub fn from_toml(s: &str) -> Result<Self, toml::de::Error> {
+        let file: CardFile = toml::from_str(s)?;
+        let mut collection = CardCollection::new();
+        for card in file.cards {
+            collection.insert(Card {
+                id: card.id,
+                name: intern(&card.name),
+                effects: card.effects.iter().map(EffectDescriptor::build).collect(),
+                target: card.target,
+                tags: card.tags,
+                cost: card.cost,
+            });
+        }
+
+        Ok(collection)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        Ok(Self::from_toml(&contents)?)
+    }
+}
+
+/// A card as it's written in a content file
+#[derive(Serialize, Deserialize)]
+struct CardDescriptor {
+    id: CardId,
+    name: String,
+    target: Target,
+    #[serde(default)]
+    tags: Vec<CardTag>,
+    cost: u32,
+    #[serde(default)]
+    effects: Vec<EffectDescriptor>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CardFile {
+    #[serde(default)]
+    cards: Vec<CardDescriptor>,
+}
+
+#[cfg(test)]
+mod test_game {
+    use super::*;
+
+    #[test]
     fn test_draw_hand() {
         // Initialize game state for the test
         let cards = CardCollection::new();
         let init_deck = vec![];
         let mut game = GameState::new(cards, init_deck);
 
-        // Drawing a hand with an empty deck should not panic
-        draw_hand(&mut game, 4);
-        assert_eq!(game.hand, vec![], "Hand should be empty");
+        // Drawing a hand with an empty deck should not panic
+        draw_hand(&mut game, 4);
+        assert!(game.hand.is_empty(), "Hand should be empty");
+
+        // Try with a draw pile of three cards and try to draw four
+        let expected_hand = vec![CardId::PHASERS, CardId::PHASERS, CardId::PHASERS];
+        game.draw = game.instances(&expected_hand);
+        draw_hand(&mut game, 4);
+        assert_eq!(expected_hand, game.hand);
+        assert!(game.draw.is_empty(), "Draw pile should be empty");
+    }
+
+    #[test]
+    fn test_draw_hand_reshuffles() {
+        let cards = CardCollection::new();
+        let init_deck = vec![CardId::SHIELDS, CardId::PHASERS];
+        let mut game = GameState::new(cards, init_deck);
+        game.discard = game.instances(&[CardId::AUTO_SHIELDS; 3]);
+
+        // The top of the draw pile is drawn first, then the discard
+        // pile is reshuffled mid-draw to fill the rest of the hand
+        draw_hand(&mut game, 5);
+        assert_eq!(
+            game.hand,
+            vec![
+                CardId::PHASERS,
+                CardId::SHIELDS,
+                CardId::AUTO_SHIELDS,
+                CardId::AUTO_SHIELDS,
+                CardId::AUTO_SHIELDS,
+            ]
+        );
+        assert!(game.draw.is_empty(), "Draw pile should be empty");
+        assert!(game.discard.is_empty(), "Discard pile should be empty");
+        assert_eq!(game.events, vec![GameEvent::DeckReshuffled]);
+
+        // Nothing left to reshuffle so no event is emitted
+        draw_hand(&mut game, 1);
+        assert_eq!(game.events, vec![GameEvent::DeckReshuffled]);
+    }
+
+    #[test]
+    fn test_card_id_from_name() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("torpedoes"),
+            name: "Torpedoes",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+
+        // IDs built from the same name are equal
+        let card_id = CardId::new("torpedoes");
+        assert_eq!(card_id.as_str(), "torpedoes");
+        assert_eq!(cards.get(&card_id).unwrap().name, "Torpedoes");
+        assert!(cards.get(&CardId::new("missing")).is_none());
+    }
+
+    #[test]
+    fn test_scry() {
+        let cards = CardCollection::new();
+        let init_deck = vec![CardId::SHIELDS, CardId::PHASERS, CardId::AUTO_SHIELDS];
+        let mut game = GameState::new(cards, init_deck);
+
+        game.actions.push_back(Action::Scry(2));
+        tick(&mut game);
+        assert_eq!(game.scry, vec![CardId::AUTO_SHIELDS, CardId::PHASERS]);
+
+        // Revealing more cards than the draw pile has shows them all
+        assert_eq!(top_of_draw(&game, 5).len(), 3);
+
+        // Discard the second card from the top
+        game.actions.push_back(Action::ScryDiscard(1));
+        tick(&mut game);
+        assert_eq!(game.scry, vec![CardId::AUTO_SHIELDS]);
+        assert_eq!(game.draw, vec![CardId::SHIELDS, CardId::AUTO_SHIELDS]);
+        assert_eq!(game.discard, vec![CardId::PHASERS]);
+
+        game.actions.push_back(Action::EndScry);
+        tick(&mut game);
+        assert!(game.scry.is_empty());
+        assert_eq!(game.draw, vec![CardId::SHIELDS, CardId::AUTO_SHIELDS]);
+
+        // Discarding past the end of the draw pile is ignored if it
+        // shrank since the scry
+        game.actions.push_back(Action::Scry(2));
+        tick(&mut game);
+        game.draw.truncate(1);
+        game.actions.push_back(Action::ScryDiscard(1));
+        tick(&mut game);
+        assert_eq!(game.draw, vec![CardId::SHIELDS]);
+        assert_eq!(game.discard, vec![CardId::PHASERS]);
+    }
+
+    #[test]
+    fn test_discard_hand() {
+        // Initialize game state for the test
+        let cards = CardCollection::new();
+        let init_deck = vec![];
+        let mut game = GameState::new(cards, init_deck);
+
+        // Try with a draw pile of three cards and try to draw four
+        game.hand = game.instances(&[CardId::PHASERS, CardId::PHASERS]);
+        discard_hand(&mut game);
+        assert!(game.hand.is_empty(), "Hand should be empty");
+        assert_eq!(
+            vec![CardId::PHASERS, CardId::PHASERS],
+            game.discard,
+            "Cards from the hand should all be in the discard pile"
+        )
+    }
+
+    #[test]
+    fn test_apply_effects() {
+        // Initialize game state for the test
+        let cards = CardCollection::new();
+        let init_deck = vec![];
+        let mut game = GameState::new(cards, init_deck);
+
+        // Add a player entity
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let player_id = game.add_entity(None, Box::new(player));
+
+        // We'll test the shields card effects are applied correctly
+        let card = Card {
+            id: CardId::SHIELDS,
+            name: "Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        };
+
+        // Apply state change for the card
+        let state_change = card.effects[0].calculate(&game, game.player, player_id);
+        game.apply_effect(player_id, (player_id, state_change));
+
+        assert_eq!(
+            game.entity_state
+                .get_mut(&player_id)
+                .unwrap()
+                .get_state()
+                .get(&Attribute::Shields)
+                .unwrap(),
+            &11i32
+        )
+    }
+
+    #[test]
+    fn test_damage_hull_amount() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 0);
+        let enemy = Enemy {
+            name: String::from("Test Enemy"),
+            state: s,
+            team: Team::Enemy,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        let enemy_id = game.add_entity(None, Box::new(enemy));
+
+        let state_change = DamageHull { amount: 3 }.calculate(&game, game.player, enemy_id);
+        game.apply_effect(enemy_id, (enemy_id, state_change));
+        let state = game.entity_state[&enemy_id].get_state();
+        assert_eq!(state.get(&Attribute::Hull), Some(&7));
+
+        // Shields absorb damage before it reaches the hull
+        game.entity_state
+            .get_mut(&enemy_id)
+            .unwrap()
+            .get_state_mut()
+            .insert(Attribute::Shields, 1);
+        let state_change = DamageHull { amount: 3 }.calculate(&game, game.player, enemy_id);
+        game.apply_effect(enemy_id, (enemy_id, state_change));
+        let state = game.entity_state[&enemy_id].get_state();
+        assert_eq!(state.get(&Attribute::Shields), Some(&0));
+        assert_eq!(state.get(&Attribute::Hull), Some(&5));
+    }
+
+    #[test]
+    fn test_target_all_hits_opposing_team() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("barrage"),
+            name: "Barrage",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::All,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let ally = Enemy {
+            name: String::from("Drone"),
+            state: s,
+            team: Team::Player,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        let ally_id = game.add_entity(None, Box::new(ally));
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_ids = spawn_enemies(&mut game, &[template.clone(), template]);
+
+        game.hand = game.instances(&[CardId::new("barrage")]);
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+
+        let hull = |id: &EntityId| {
+            *game.entity_state[id]
+                .get_state()
+                .get(&Attribute::Hull)
+                .unwrap()
+        };
+        for enemy_id in enemy_ids.iter() {
+            assert_eq!(hull(enemy_id), 9);
+        }
+        assert_eq!(hull(&game.player), 10, "Player should not be hit");
+        assert_eq!(hull(&ally_id), 10, "Ally should not be hit");
+    }
+
+    #[test]
+    fn test_preview_card() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 }), Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let enemy = Enemy {
+            name: String::from("Test Enemy"),
+            state: s,
+            team: Team::Enemy,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        let enemy_id = game.add_entity(None, Box::new(enemy));
+
+        let preview = preview_card(&game, &CardId::PHASERS, enemy_id).unwrap();
+        assert_eq!(preview.get(&Attribute::Hull), Some(&-2));
+
+        // Previewing should not change the target
+        assert_eq!(
+            game.entity_state[&enemy_id].get_state().get(&Attribute::Hull),
+            Some(&10)
+        );
+    }
+
+    #[test]
+    fn test_apply_effect_to_destroyed_entity() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 1);
+        let enemy = Enemy {
+            name: String::from("Test Enemy"),
+            state: s,
+            team: Team::Enemy,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        let enemy_id = game.add_entity(None, Box::new(enemy));
+
+        let mut damage = State::new();
+        damage.insert(Attribute::Hull, -1);
+        game.apply_effect(enemy_id, (enemy_id, damage.clone()));
+        assert!(!game.entity_state.contains_key(&enemy_id));
+
+        // A follow up effect against the destroyed entity is ignored
+        game.apply_effect(enemy_id, (enemy_id, damage));
+        assert!(game.entities.is_empty());
+    }
+
+    #[test]
+    fn test_spawn_enemies() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        let templates = vec![
+            EnemyTemplate {
+                name: "Frigate",
+                hull: 5,
+                shields: 2,
+                ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(1)]),
+                art: None,
+            },
+            EnemyTemplate {
+                name: "Battleship",
+                hull: 10,
+                shields: 10,
+                ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(3)]),
+                art: None,
+            },
+        ];
+
+        let ids = spawn_enemies(&mut game, &templates);
+        assert_eq!(ids.len(), 2);
+        assert_eq!(game.enemy, Some(ids[0]));
+
+        for (id, template) in ids.iter().zip(templates.iter()) {
+            let enemy = &game.entity_state[id];
+            assert_eq!(enemy.name(), template.name);
+            assert_eq!(enemy.get_state().get(&Attribute::Hull), Some(&template.hull));
+            assert_eq!(enemy.get_state().get(&Attribute::Shields), Some(&template.shields));
+        }
+    }
+
+    #[test]
+    fn test_enemy_turn() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 5,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(1), EnemyIntent::Attack(2)]),
+            art: None,
+        };
+        spawn_enemies(&mut game, &[template]);
+
+        // Enemies act at the end of the player's turn cycling
+        // through their intents
+        let player_hull = |game: &GameState| {
+            *game.entity_state[&game.player]
+                .get_state()
+                .get(&Attribute::Hull)
+                .unwrap()
+        };
+        for expected_hull in [9, 7, 6] {
+            game.actions.push_back(Action::EndTurn);
+            tick(&mut game);
+            assert_eq!(player_hull(&game), expected_hull);
+        }
+    }
+
+    #[test]
+    fn test_export_log() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        game.events.push(GameEvent::DeckReshuffled);
+        game.events.push(GameEvent::CardPlayed {
+            card_id: CardId::PHASERS,
+            target: 2,
+        });
+        game.events.push(GameEvent::EnemyActed {
+            enemy: 2,
+            intent: EnemyIntent::Attack(3),
+        });
+
+        let path = std::env::temp_dir().join("tunnelcast_test_export_log.toml");
+        game.export_log(&path).unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let log: CombatLog = toml::from_str(&contents).unwrap();
+        assert!(log.exported_at > 0);
+        assert_eq!(log.events, game.events);
+    }
+
+    #[test]
+    fn test_deflector_field_retaliates() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 1);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::DeflectorField, 2);
+        let enemy = Enemy {
+            name: String::from("Test Enemy"),
+            state: s,
+            team: Team::Enemy,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        let enemy_id = game.add_entity(None, Box::new(enemy));
+
+        game.hand = game.instances(&[CardId::PHASERS]);
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+
+        let enemy_state = game.entity_state[&enemy_id].get_state();
+        assert_eq!(enemy_state.get(&Attribute::Hull), Some(&9));
+
+        // The player's shields absorb part of the retaliation
+        let player_state = game.entity_state[&game.player].get_state();
+        assert_eq!(player_state.get(&Attribute::Shields), Some(&0));
+        assert_eq!(player_state.get(&Attribute::Hull), Some(&9));
+    }
+
+    #[test]
+    fn test_turn_counter() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        assert_eq!(game.turn, 0);
+
+        for action in [Action::BeginTurn, Action::Draw, Action::EndTurn] {
+            game.actions.push_back(action);
+            tick(&mut game);
+            assert_eq!(game.turn, 1);
+        }
+
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.turn, 2);
+    }
+
+    #[test]
+    fn test_outcome() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let player_id = game.add_entity(None, Box::new(player));
+        game.player = player_id;
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 1);
+        let enemy = Enemy {
+            name: String::from("Test Enemy"),
+            state: s,
+            team: Team::Enemy,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        let enemy_id = game.add_entity(None, Box::new(enemy));
+        game.enemy = Some(enemy_id);
+
+        assert_eq!(game.outcome(), None, "Combat should still be in progress");
+
+        // Destroying the last enemy wins
+        let mut damage = State::new();
+        damage.insert(Attribute::Hull, -1);
+        game.apply_effect(enemy_id, (enemy_id, damage));
+        assert_eq!(game.enemy, None);
+        assert_eq!(game.outcome(), Some(Outcome::Victory));
+
+        // Losing the player loses even with no enemies left
+        let mut damage = State::new();
+        damage.insert(Attribute::Hull, -10);
+        game.apply_effect(player_id, (player_id, damage));
+        assert_eq!(game.outcome(), Some(Outcome::Defeat));
+    }
+
+    #[test]
+    fn test_integration() {
+        let mut cards = CardCollection::new();
+
+        cards.insert(Card {
+            id: CardId::SHIELDS,
+            name: "Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+
+        let mut init_deck = vec![
+            CardId::SHIELDS,
+            CardId::SHIELDS,
+            CardId::SHIELDS,
+            CardId::PHASERS,
+            CardId::PHASERS,
+            CardId::PHASERS,
+        ];
+        shuffle_deck(&mut init_deck, &mut rand::thread_rng());
+
+        let mut game = GameState::new(cards, init_deck);
+
+        // Add an enemy
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 10);
+        let enemy = Enemy {
+            name: String::from("Test Enemy"),
+            state: s,
+            team: Team::Enemy,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        let enemy_id = game.add_entity(None, Box::new(enemy));
+
+        // Run through a turn to make sure it works
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+
+        game.actions.push_back(Action::EndTurn);
+        tick(&mut game);
+    }
+
+    #[test]
+    fn test_animation_phase() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::SHIELDS,
+            name: "Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let player_id = game.add_entity(None, Box::new(player));
+        game.player = player_id;
+
+        game.hand = game.instances(&[CardId::SHIELDS]);
+        game.actions.push_back(Action::PlayCard(player_id, 0));
+        tick(&mut game);
+        assert_eq!(game.phase, TurnPhase::Animating(ANIMATION_FRAMES));
+        assert!(!game.awaiting_input());
+
+        // Actions are not processed until the animation finishes
+        game.hand = game.instances(&[CardId::SHIELDS]);
+        game.actions.push_back(Action::PlayCard(player_id, 0));
+        tick(&mut game);
+        assert_eq!(game.hand, vec![CardId::SHIELDS]);
+        assert_eq!(game.phase, TurnPhase::Animating(ANIMATION_FRAMES - 1));
+
+        for _ in 1..ANIMATION_FRAMES {
+            tick(&mut game);
+        }
+        assert!(game.awaiting_input());
+    }
+
+    #[test]
+    fn test_power_fires_each_turn() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::AUTO_SHIELDS,
+            name: "Auto-Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Power],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let player_id = game.add_entity(None, Box::new(player));
+        game.player = player_id;
+
+        // Playing the power installs it instead of resolving it
+        game.hand = game.instances(&[CardId::AUTO_SHIELDS]);
+        game.actions.push_back(Action::PlayCard(player_id, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert!(game.hand.is_empty(), "Power should leave the hand");
+        assert!(game.discard.is_empty(), "Power should not be discarded");
+        assert_eq!(game.powers, vec![(player_id, CardId::AUTO_SHIELDS)]);
+
+        let shields = |game: &GameState| {
+            *game.entity_state[&player_id]
+                .get_state()
+                .get(&Attribute::Shields)
+                .unwrap()
+        };
+        assert_eq!(shields(&game), 10, "Power should not resolve when played");
+
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(shields(&game), 11);
+
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(shields(&game), 12);
+    }
+
+    #[test]
+    fn test_power_resolves_effects() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::AUTO_SHIELDS,
+            name: "Auto-Shields",
+            effects: vec![Box::new(DiscountNextCard { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Power],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let player_id = game.add_entity(None, Box::new(player));
+        game.player = player_id;
+        game.powers.push((player_id, CardId::AUTO_SHIELDS));
+
+        // Effects that only resolve still fire for powers
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.next_card_discount, 1);
+    }
+
+    #[test]
+    fn test_power_block_survives() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::AUTO_SHIELDS,
+            name: "Auto-Shields",
+            effects: vec![Box::new(IncreaseBlock { amount: 3 })],
+            target: Target::Player,
+            tags: vec![CardTag::Power],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Block, 5);
+        let player = Player { name: String::from("Player"), state: s };
+        let player_id = game.add_entity(None, Box::new(player));
+        game.player = player_id;
+        game.powers.push((player_id, CardId::AUTO_SHIELDS));
+
+        // Last turn's block expires but the power's block is kept
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&player_id, Attribute::Block), 3);
+    }
+
+    #[test]
+    fn test_replay() {
+        let setup = |seed: u64| {
+            let mut cards = CardCollection::new();
+            cards.insert(Card {
+                id: CardId::SHIELDS,
+                name: "Shields",
+                effects: vec![Box::new(IncreaseShields { amount: 1 })],
+                target: Target::Player,
+                tags: vec![CardTag::Skill],
+                cost: 1,
+            });
+            cards.insert(Card {
+                id: CardId::PHASERS,
+                name: "Phasers",
+                effects: vec![Box::new(DamageHull { amount: 1 })],
+                target: Target::Single,
+                tags: vec![CardTag::Attack],
+                cost: 1,
+            });
+            let deck = vec![
+                CardId::SHIELDS,
+                CardId::SHIELDS,
+                CardId::SHIELDS,
+                CardId::PHASERS,
+                CardId::PHASERS,
+                CardId::PHASERS,
+            ];
+            let mut game = GameState::with_seed(cards, deck, seed);
+            game.shuffle_draw();
+
+            let mut s = State::new();
+            s.insert(Attribute::Hull, 10);
+            s.insert(Attribute::Shields, 2);
+            let player = Player { name: String::from("Player"), state: s };
+            game.player = game.add_entity(None, Box::new(player));
+
+            let template = EnemyTemplate {
+                name: "Frigate",
+                hull: 10,
+                shields: 1,
+                ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(2), EnemyIntent::Attack(1)]),
+                art: None,
+            };
+            spawn_enemies(&mut game, &[template]);
+            game
+        };
+
+        // Play two turns so the discard pile gets reshuffled
+        let seed = 42;
+        let mut game = setup(seed);
+        let enemy_id = game.enemy.unwrap();
+        for action in [
+            Action::BeginTurn,
+            Action::PlayCard(enemy_id, 0),
+            Action::PlayCard(enemy_id, 1),
+            Action::EndTurn,
+            Action::BeginTurn,
+            Action::PlayCard(enemy_id, 0),
+            Action::EndTurn,
+        ] {
+            game.actions.push_back(action);
+            tick(&mut game);
+            game.phase = TurnPhase::PlayerInput;
+        }
+        assert_eq!(game.history.len(), 7);
+        assert!(game.events.contains(&GameEvent::DeckReshuffled));
+
+        let replayed = GameState::replay(seed, &game.history, setup);
+        assert_eq!(replayed.entities, game.entities);
+        for id in game.entities.iter() {
+            assert_eq!(
+                replayed.entity_state[id].get_state(),
+                game.entity_state[id].get_state()
+            );
+        }
+        assert_eq!(replayed.hand, game.hand);
+        assert_eq!(replayed.draw, game.draw);
+        assert_eq!(replayed.discard, game.discard);
+        assert_eq!(replayed.history, game.history);
+    }
+
+    #[test]
+    fn test_valid_targets() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 1,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_ids = spawn_enemies(&mut game, &[template.clone(), template]);
+        assert_eq!(Target::Single.valid_targets(&game), enemy_ids);
+        assert_eq!(Target::Player.valid_targets(&game), vec![game.player]);
+
+        // Destroyed enemies can no longer be targeted
+        let state_change = DamageHull { amount: 1 }.calculate(&game, game.player, enemy_ids[0]);
+        game.apply_effect(game.player, (enemy_ids[0], state_change));
+        let targets = Target::Single.valid_targets(&game);
+        assert_eq!(targets, vec![enemy_ids[1]]);
+        assert!(!targets.contains(&game.player));
+    }
+
+    #[test]
+    fn test_attribute_floor() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 3);
+        s.insert(Attribute::Shields, 2);
+        let enemy = Enemy {
+            name: String::from("Test Enemy"),
+            state: s,
+            team: Team::Enemy,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        let enemy_id = game.add_entity(None, Box::new(enemy));
+
+        // Shields can't go below zero
+        let mut change = State::new();
+        change.insert(Attribute::Shields, -5);
+        game.apply_effect(enemy_id, (enemy_id, change));
+        let state = game.entity_state[&enemy_id].get_state();
+        assert_eq!(state.get(&Attribute::Shields), Some(&0));
+
+        // Hull reaches zero which destroys the entity
+        let mut change = State::new();
+        change.insert(Attribute::Hull, -3);
+        game.apply_effect(enemy_id, (enemy_id, change));
+        assert!(!game.entity_state.contains_key(&enemy_id));
+    }
+
+    #[test]
+    fn test_entity_destroyed_event() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 2,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+
+        let mut damage = State::new();
+        damage.insert(Attribute::Hull, -1);
+        game.apply_effect(game.player, (enemy_id, damage.clone()));
+        assert!(game.events.is_empty(), "Enemy should survive the first hit");
+
+        // Further damage after it's destroyed doesn't emit again
+        game.apply_effect(game.player, (enemy_id, damage.clone()));
+        game.apply_effect(game.player, (enemy_id, damage));
+        assert_eq!(game.events, vec![GameEvent::EntityDestroyed { id: enemy_id }]);
+    }
+
+    #[test]
+    fn test_card_collection_iter() {
+        let mut cards = CardCollection::new();
+        for (id, name) in [
+            (CardId::SHIELDS, "Shields"),
+            (CardId::PHASERS, "Phasers"),
+            (CardId::AUTO_SHIELDS, "Auto-Shields"),
+        ] {
+            cards.insert(Card {
+                id,
+                name,
+                effects: vec![],
+                target: Target::Player,
+                tags: vec![CardTag::Skill],
+                cost: 1,
+            });
+        }
+
+        let mut ids = cards.all_ids();
+        ids.sort_by_key(|id| id.as_str());
+        assert_eq!(ids, vec![CardId::AUTO_SHIELDS, CardId::PHASERS, CardId::SHIELDS]);
+
+        let mut names: Vec<&str> = cards.iter().map(|(_, card)| card.name).collect();
+        names.sort();
+        assert_eq!(names, vec!["Auto-Shields", "Phasers", "Shields"]);
+        assert!(cards.iter().all(|(id, card)| *id == card.id));
+    }
+
+    #[test]
+    fn test_discard_from_hand() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        game.hand = game.instances(&[CardId::SHIELDS, CardId::PHASERS, CardId::AUTO_SHIELDS]);
+
+        game.actions.push_back(Action::DiscardFromHand(1));
+        tick(&mut game);
+        assert_eq!(game.hand, vec![CardId::SHIELDS, CardId::AUTO_SHIELDS]);
+        assert_eq!(game.discard, vec![CardId::PHASERS]);
+
+        // An index past the end of the hand is ignored
+        game.actions.push_back(Action::DiscardFromHand(5));
+        tick(&mut game);
+        assert_eq!(game.hand.len(), 2);
+    }
+
+    #[test]
+    fn test_discard_random() {
+        let mut game = GameState::with_seed(CardCollection::new(), vec![], 7);
+        game.hand = game.instances(&[CardId::SHIELDS, CardId::PHASERS, CardId::AUTO_SHIELDS]);
+
+        game.actions.push_back(Action::DiscardRandom(2));
+        tick(&mut game);
+        assert_eq!(game.hand.len(), 1);
+        assert_eq!(game.discard.len(), 2);
+        assert!(!game.discard.contains(&game.hand[0]));
+
+        // Discarding more cards than are in the hand empties it
+        game.actions.push_back(Action::DiscardRandom(3));
+        tick(&mut game);
+        assert!(game.hand.is_empty());
+        assert_eq!(game.discard.len(), 3);
+    }
+
+    #[test]
+    fn test_combo() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+        game.combos.push(Combo {
+            previous: CardId::PHASERS,
+            card: CardId::PHASERS,
+            bonus: vec![Box::new(DamageHull { amount: 1 })],
+        });
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+        let hull = |game: &GameState| {
+            *game.entity_state[&enemy_id]
+                .get_state()
+                .get(&Attribute::Hull)
+                .unwrap()
+        };
+        let play = |game: &mut GameState, action: Action| {
+            game.actions.push_back(action);
+            tick(game);
+            game.phase = TurnPhase::PlayerInput;
+        };
+
+        // The second Phasers in a row deals bonus damage
+        game.hand = game.instances(&[CardId::PHASERS, CardId::PHASERS]);
+        play(&mut game, Action::PlayCard(enemy_id, 0));
+        assert_eq!(hull(&game), 9);
+        play(&mut game, Action::PlayCard(enemy_id, 0));
+        assert_eq!(hull(&game), 7);
+
+        // The combo doesn't carry over to the next turn
+        play(&mut game, Action::EndTurn);
+        game.hand = game.instances(&[CardId::PHASERS]);
+        play(&mut game, Action::PlayCard(enemy_id, 0));
+        assert_eq!(hull(&game), 6);
+    }
+
+    #[test]
+    fn test_legal_actions() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::SHIELDS,
+            name: "Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 3 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 2,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+
+        game.hand = game.instances(&[CardId::SHIELDS, CardId::PHASERS]);
+        game.energy = 1;
+        assert_eq!(
+            game.legal_actions(),
+            vec![Action::PlayCard(game.player, 0), Action::EndTurn]
+        );
+
+        game.energy = 2;
+        assert_eq!(
+            game.legal_actions(),
+            vec![
+                Action::PlayCard(game.player, 0),
+                Action::PlayCard(enemy_id, 1),
+                Action::EndTurn
+            ]
+        );
+
+        // Playing a card spends energy and unaffordable cards are
+        // ignored
+        game.actions.push_back(Action::PlayCard(enemy_id, 1));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert_eq!(game.energy, 0);
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+        assert_eq!(game.hand, vec![CardId::SHIELDS]);
+
+        // Energy is refilled at the start of the turn
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.energy, game.max_energy);
+    }
+
+    #[test]
+    fn test_strength_boosts_damage() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("power_surge"),
+            name: "Power Surge",
+            effects: vec![Box::new(IncreaseStrength { amount: 2 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+
+        game.hand = game.instances(&[CardId::new("power_surge"), CardId::PHASERS]);
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert_eq!(game.attribute(&game.player, Attribute::Strength), 2);
+
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 7);
+    }
+
+    #[test]
+    fn test_dexterity_boosts_shields() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Dexterity, 1);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let state_change = IncreaseShields { amount: 2 }.calculate(&game, game.player, game.player);
+        assert_eq!(state_change.get(&Attribute::Shields), Some(&3));
+    }
+
+    #[test]
+    fn test_invalid_target_is_an_error() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let missing_id = 404;
+        assert_eq!(
+            Target::Single.resolve(&game, game.player),
+            Err(GameError::InvalidTarget(game.player))
+        );
+        assert_eq!(
+            Target::Single.resolve(&game, missing_id),
+            Err(GameError::InvalidTarget(missing_id))
+        );
+        assert_eq!(game.entity(&missing_id).err(), Some(GameError::EntityNotFound(missing_id)));
+        assert_eq!(
+            game.try_apply_effect(game.player, (missing_id, State::new())),
+            Err(GameError::EntityNotFound(missing_id))
+        );
+        assert_eq!(game.remove_entity(&missing_id), Err(GameError::EntityNotFound(missing_id)));
+        assert!(preview_card(&game, &CardId::new("missing"), game.player).is_err());
+
+        // Playing a card against an invalid target leaves it in the hand
+        game.hand = game.instances(&[CardId::PHASERS]);
+        game.actions.push_back(Action::PlayCard(missing_id, 0));
+        tick(&mut game);
+        assert_eq!(game.hand, vec![CardId::PHASERS]);
+        assert!(game.awaiting_input());
+    }
+
+    #[test]
+    fn test_shuffle_with_seed() {
+        let deck = vec![CardId::SHIELDS, CardId::PHASERS, CardId::AUTO_SHIELDS, CardId::PHASERS];
+
+        let mut shuffled = deck.clone();
+        shuffle_deck(&mut shuffled, &mut StdRng::seed_from_u64(1));
+        assert_eq!(
+            shuffled,
+            vec![CardId::PHASERS, CardId::AUTO_SHIELDS, CardId::SHIELDS, CardId::PHASERS]
+        );
+
+        // The identity strategy leaves the deck alone
+        let mut unshuffled = deck.clone();
+        Shuffle::Identity.apply(&mut unshuffled, &mut StdRng::seed_from_u64(1));
+        assert_eq!(unshuffled, deck);
+    }
+
+    #[test]
+    fn test_enemy_defends() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 5,
+            shields: 2,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Defend(3)]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+
+        game.actions.push_back(Action::EndTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Shields), 5);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 10);
+        assert_eq!(
+            game.events,
+            vec![GameEvent::EnemyActed {
+                enemy: enemy_id,
+                intent: EnemyIntent::Defend(3)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_entity_name() {
+        let player: Box<dyn Entity> = Box::new(Player {
+            name: String::from("Player"),
+            state: State::new(),
+        });
+        let enemy: Box<dyn Entity> = Box::new(Enemy {
+            name: String::from("Frigate"),
+            state: State::new(),
+            team: Team::Enemy,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        });
+
+        assert_eq!(player.name(), "Player");
+        assert_eq!(enemy.name(), "Frigate");
+    }
+
+    #[test]
+    fn test_summon() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("deploy_drone"),
+            name: "Deploy Drone",
+            effects: vec![Box::new(Summon {
+                template: EnemyTemplate {
+                    name: "Drone",
+                    hull: 3,
+                    shields: 1,
+                    ai: EnemyAi::Cycle(vec![]),
+                    art: None,
+                },
+            })],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        game.hand = game.instances(&[CardId::new("deploy_drone")]);
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+
+        assert_eq!(game.entities.len(), 2);
+        let drone_id = game.entities[1];
+        let drone = &game.entity_state[&drone_id];
+        assert_eq!(drone.name(), "Drone");
+        assert_eq!(drone.get_team(), Team::Player);
+        assert_eq!(drone.get_state().get(&Attribute::Hull), Some(&3));
+        assert_eq!(drone.get_state().get(&Attribute::Shields), Some(&1));
+        assert!(game.cards.get(&CardId::new("deploy_drone")).is_some());
+    }
+
+    #[test]
+    fn test_next_card_discount() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("overclock"),
+            name: "Overclock",
+            effects: vec![Box::new(DiscountNextCard { amount: 3 })],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 1,
+        });
+        cards.insert(Card {
+            id: CardId::SHIELDS,
+            name: "Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 5 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 3,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        game.energy = 1;
+        game.hand = game.instances(&[CardId::new("overclock"), CardId::SHIELDS, CardId::SHIELDS]);
+        assert!(!game.can_afford(1), "Shields should be too expensive");
+
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert_eq!(game.energy, 0);
+        assert_eq!(game.card_cost(0), Some(0));
+
+        // The next card is free
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 5);
+
+        // The discount is used up
+        assert_eq!(game.next_card_discount, 0);
+        assert!(!game.can_afford(0));
+    }
+
+    #[test]
+    fn test_deck_composition() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        game.draw = game.instances(&[CardId::SHIELDS, CardId::PHASERS]);
+        game.hand = game.instances(&[CardId::PHASERS, CardId::AUTO_SHIELDS]);
+        game.discard = game.instances(&[CardId::SHIELDS, CardId::PHASERS]);
+
+        let composition = game.deck_composition();
+        assert_eq!(composition.len(), 3);
+        assert_eq!(composition[&CardId::SHIELDS], 2);
+        assert_eq!(composition[&CardId::PHASERS], 3);
+        assert_eq!(composition[&CardId::AUTO_SHIELDS], 1);
+    }
+
+    #[test]
+    fn test_salvage() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("salvage"),
+            name: "Salvage",
+            effects: vec![Box::new(SalvageDiscard)],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        game.hand = game.instances(&[CardId::new("salvage")]);
+        game.discard = game.instances(&[CardId::SHIELDS, CardId::PHASERS]);
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert!(game.salvaging);
+
+        // Salvage is on top of the discard pile so Phasers is second
+        game.actions.push_back(Action::SalvageCard(1));
+        tick(&mut game);
+        assert!(!game.salvaging);
+        assert_eq!(game.hand, vec![CardId::PHASERS]);
+        assert_eq!(game.discard, vec![CardId::SHIELDS, CardId::new("salvage")]);
+
+        // Salvaging from an empty discard pile does nothing
+        game.discard.clear();
+        game.actions.push_back(Action::SalvageCard(0));
+        tick(&mut game);
+        assert_eq!(game.hand, vec![CardId::PHASERS]);
+        assert!(game.discard.is_empty());
+    }
+
+    #[test]
+    fn test_draw_until() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("recalibrate"),
+            name: "Recalibrate",
+            effects: vec![Box::new(DrawUntil { target_size: 4 })],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        // The played card leaves the hand first so three are drawn to
+        // fill the hand to four
+        game.hand = game.instances(&[CardId::new("recalibrate"), CardId::SHIELDS]);
+        game.draw = game.instances(&[CardId::PHASERS; 5]);
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert_eq!(game.hand.len(), 4);
+        assert_eq!(game.draw.len(), 2);
+
+        // Drawing stops once the draw and discard piles run out. The
+        // played card was reshuffled into the draw pile and drawn.
+        game.hand = game.instances(&[CardId::new("recalibrate")]);
+        game.draw = game.instances(&[CardId::PHASERS]);
+        game.discard.clear();
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+        assert_eq!(game.hand, vec![CardId::PHASERS, CardId::new("recalibrate")]);
+        assert!(game.draw.is_empty());
+        assert!(game.discard.is_empty());
+
+        // The hand never grows past the max hand size
+        game.hand.clear();
+        game.draw = game.instances(&[CardId::PHASERS; MAX_HAND_SIZE + 2]);
+        draw_until(&mut game, MAX_HAND_SIZE + 2);
+        assert_eq!(game.hand.len(), MAX_HAND_SIZE);
+        assert_eq!(game.draw.len(), 2);
+    }
+
+    #[test]
+    fn test_resolve_effects() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("strafe"),
+            name: "Strafe",
+            effects: vec![
+                Box::new(DamageHull { amount: 3 }),
+                Box::new(DrawUntil { target_size: 2 }),
+            ],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let frigate = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(cards, vec![])
+            .player(player)
+            .enemies(std::slice::from_ref(&frigate))
+            .build();
+        let enemy_id = game.enemy.unwrap();
+
+        // A card can mix effects that change attributes with effects
+        // that move cards between piles
+        game.hand = game.instances(&[CardId::new("strafe")]);
+        game.draw = game.instances(&[CardId::SHIELDS, CardId::PHASERS, CardId::PHASERS]);
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 7);
+        assert_eq!(game.hand, vec![CardId::PHASERS, CardId::PHASERS]);
+        assert_eq!(game.draw, vec![CardId::SHIELDS]);
+
+        // Costs are paid by whoever cast the effect
+        let ally_id = spawn(&mut game, &frigate, Team::Player);
+        let sacrifice = Sacrifice {
+            hull_cost: 4,
+            amount: 2,
+            allow_lethal: false,
+        };
+        sacrifice.resolve(&mut game, ally_id, enemy_id);
+        assert_eq!(game.attribute(&ally_id, Attribute::Hull), 6);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 10);
+    }
+
+    #[test]
+    fn test_weighted_intents() {
+        let mut enemy = Enemy {
+            name: String::from("Frigate"),
+            state: State::new(),
+            team: Team::Enemy,
+            ai: EnemyAi::Weighted(vec![
+                (EnemyIntent::Attack(1), 1),
+                (EnemyIntent::Defend(2), 3),
+                (EnemyIntent::Attack(5), 0),
+            ]),
+            ai_step: 0,
+            art: None,
+        };
+
+        // Intents with no weight are never picked
+        let mut rng = StdRng::seed_from_u64(3);
+        let intents: Vec<EnemyIntent> =
+            (0..6).filter_map(|_| enemy.next_intent(&mut rng)).collect();
+        assert_eq!(
+            intents,
+            vec![
+                EnemyIntent::Defend(2),
+                EnemyIntent::Defend(2),
+                EnemyIntent::Defend(2),
+                EnemyIntent::Attack(1),
+                EnemyIntent::Defend(2),
+                EnemyIntent::Attack(1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_action_queue() {
+        let deck = vec![CardId::SHIELDS, CardId::PHASERS];
+        let mut game = GameState::new(CardCollection::new(), deck);
+
+        // Both actions resolve in order in the same tick
+        game.actions.push_back(Action::Draw);
+        game.actions.push_back(Action::DiscardFromHand(0));
+        tick(&mut game);
+        assert!(game.actions.is_empty());
+        assert!(game.hand.is_empty());
+        assert_eq!(game.discard, vec![CardId::PHASERS]);
+        assert_eq!(game.history, vec![Action::Draw, Action::DiscardFromHand(0)]);
+    }
+
+    #[test]
+    fn test_piercing_damage_ignores_shields() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 20,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+
+        let state_change = DamagePiercing { amount: 3 }.calculate(&game, game.player, enemy_id);
+        game.apply_effect(game.player, (enemy_id, state_change));
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 7);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Shields), 20);
+    }
+
+    fn evasive_target(game: &mut GameState, evasion: i32) -> EntityId {
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 100);
+        s.insert(Attribute::Evasion, evasion);
+        let enemy = Enemy {
+            name: String::from("Interceptor"),
+            state: s,
+            team: Team::Enemy,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        game.add_entity(None, Box::new(enemy))
+    }
+
+    /// Attack the target `times` and return how many attacks missed
+    fn dodges(game: &mut GameState, target: EntityId, times: i32) -> i32 {
+        let mut dodged = 0;
+        for _ in 0..times {
+            let hull = game.attribute(&target, Attribute::Hull);
+            let state_change = damage(game.entity(&target).unwrap().get_state(), 1);
+            game.apply_effect(game.player, (target, state_change));
+            if game.attribute(&target, Attribute::Hull) == hull {
+                dodged += 1;
+            }
+        }
+        dodged
+    }
+
+    #[test]
+    fn test_evasion() {
+        let mut game = GameState::with_seed(CardCollection::new(), vec![], 1);
+        let never = evasive_target(&mut game, 0);
+        assert_eq!(dodges(&mut game, never, 10), 0);
+
+        let always = evasive_target(&mut game, 100);
+        assert_eq!(dodges(&mut game, always, 10), 10);
+
+        let sometimes = evasive_target(&mut game, 50);
+        assert_eq!(dodges(&mut game, sometimes, 10), 4);
+    }
+
+    #[test]
+    fn test_fatigue() {
+        let mut game = GameState::new(CardCollection::new(), vec![CardId::SHIELDS]);
+        game.config.fatigue = true;
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        // Drawing the last card is free
+        draw_hand(&mut game, 1);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 10);
+
+        // Each failed draw deals one more damage than the last
+        // ignoring shields
+        draw_hand(&mut game, 1);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 9);
+        draw_hand(&mut game, 2);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 4);
+        assert_eq!(game.fatigue, 3);
+        assert_eq!(game.events.last(), Some(&GameEvent::Fatigue { damage: 3 }));
+    }
+
+    #[test]
+    fn test_clone_game_state() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::with_seed(cards, vec![CardId::PHASERS], 1);
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 5,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(1)]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+        draw_hand(&mut game, 1);
+
+        let mut lookahead = game.clone();
+        lookahead.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut lookahead);
+
+        assert_eq!(lookahead.attribute(&enemy_id, Attribute::Hull), 4);
+        assert!(lookahead.hand.is_empty());
+
+        // The original game is untouched
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 5);
+        assert_eq!(game.hand, vec![CardId::PHASERS]);
+        assert!(game.actions.is_empty());
+
+        // Both games share the same future randomness
+        assert_eq!(game.rng.gen::<u32>(), lookahead.rng.gen::<u32>());
+    }
+
+    #[test]
+    fn test_attribute_descriptions() {
+        for attribute in Attribute::ALL.iter() {
+            assert!(!attribute.description().is_empty(), "{} has no description", attribute);
+        }
+    }
+
+    #[test]
+    fn test_cards_played_this_turn() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 0,
+        });
+        cards.insert(Card {
+            id: CardId::new("momentum"),
+            name: "Momentum",
+            effects: vec![Box::new(DamagePerCardPlayed { amount: 2 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 0,
+        });
+        let mut game = GameState::new(cards, vec![]);
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 20,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+
+        game.hand = game.instances(&[CardId::PHASERS, CardId::PHASERS, CardId::new("momentum")]);
+        for _ in 0..3 {
+            game.actions.push_back(Action::PlayCard(enemy_id, 0));
+            tick(&mut game);
+            game.phase = TurnPhase::PlayerInput;
+        }
+        assert_eq!(game.cards_played_this_turn, 3);
+
+        // Two phasers then momentum scaled by the two cards before it
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 20 - 1 - 1 - 4);
+
+        game.actions.push_back(Action::EndTurn);
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.cards_played_this_turn, 0);
+    }
+
+    #[test]
+    fn test_difficulty_scales_enemies() {
+        let templates = [EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 4,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(2), EnemyIntent::Defend(2)]),
+            art: None,
+        }];
+
+        let mut normal = GameState::new(CardCollection::new(), vec![]);
+        let normal_id = spawn_enemies(&mut normal, &templates)[0];
+        assert_eq!(normal.attribute(&normal_id, Attribute::Hull), 10);
+
+        let mut hard = GameState::new(CardCollection::new(), vec![]);
+        hard.config.difficulty = Difficulty::Hard;
+        let hard_id = spawn_enemies(&mut hard, &templates)[0];
+        assert_eq!(hard.attribute(&hard_id, Attribute::Hull), 15);
+        assert_eq!(hard.attribute(&hard_id, Attribute::MaxHull), 15);
+        assert_eq!(hard.attribute(&hard_id, Attribute::Shields), 6);
+
+        // Attacks hit harder but defending is unchanged
+        let mut rng = StdRng::seed_from_u64(0);
+        let enemy = hard.entity_state.get_mut(&hard_id).unwrap();
+        assert_eq!(enemy.next_intent(&mut rng), Some(EnemyIntent::Attack(3)));
+        assert_eq!(enemy.next_intent(&mut rng), Some(EnemyIntent::Defend(2)));
+    }
+
+    #[test]
+    fn test_should_confirm_end_turn() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::SHIELDS,
+            name: "Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 2,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        // Nothing to play
+        assert!(!game.should_confirm_end_turn());
+
+        game.hand = game.instances(&[CardId::SHIELDS]);
+        assert!(game.should_confirm_end_turn());
+
+        // Energy left over but not enough for the card
+        game.energy = 1;
+        assert!(!game.should_confirm_end_turn());
+
+        game.energy = 0;
+        assert!(!game.should_confirm_end_turn());
+    }
+
+    #[test]
+    fn test_run_stats() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 3 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 0,
+        });
+        let deck = vec![CardId::PHASERS; 4];
+        let mut game = GameState::with_seed(cards, deck, 1);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let templates = [
+            EnemyTemplate {
+                name: "Frigate",
+                hull: 3,
+                shields: 2,
+                ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(1)]),
+                art: None,
+            },
+            EnemyTemplate {
+                name: "Cruiser",
+                hull: 10,
+                shields: 0,
+                ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(1)]),
+                art: None,
+            },
+        ];
+        let ids = spawn_enemies(&mut game, &templates);
+
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        for target in [ids[0], ids[0], ids[1]].iter() {
+            game.actions.push_back(Action::PlayCard(*target, 0));
+            tick(&mut game);
+            game.phase = TurnPhase::PlayerInput;
+        }
+        game.actions.push_back(Action::EndTurn);
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+
+        // Overkill on the frigate counts towards damage dealt but
+        // damage taken by the player doesn't
+        assert_eq!(
+            game.stats,
+            RunStats {
+                damage_dealt: 3 + 3 + 3,
+                cards_played: 3,
+                turns_taken: 2,
+                enemies_destroyed: 1,
+            }
+        );
+    }
+
+    fn sacrifice_game(allow_lethal: bool) -> (GameState, EntityId) {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("overload_reactor"),
+            name: "Overload Reactor",
+            effects: vec![Box::new(Sacrifice { hull_cost: 3, amount: 8, allow_lethal })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 5);
+        s.insert(Attribute::Shields, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 20,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+        game.hand = game.instances(&[CardId::new("overload_reactor"); 2]);
+
+        (game, enemy_id)
+    }
+
+    #[test]
+    fn test_sacrifice() {
+        let (mut game, enemy_id) = sacrifice_game(false);
+
+        // The cost is paid from the hull ignoring shields
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 2);
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 10);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 12);
+
+        // Paying again would destroy the player
+        let energy = game.energy;
+        let card_id = game.hand[0].card_id;
+        assert_eq!(play_card(&mut game, enemy_id, 0), Err(GameError::CannotPayCost(card_id)));
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 2);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 12);
+        assert_eq!(game.hand.len(), 1);
+        assert_eq!(game.energy, energy);
+    }
+
+    #[test]
+    fn test_lethal_sacrifice() {
+        let (mut game, enemy_id) = sacrifice_game(true);
+        game.entity_state
+            .get_mut(&game.player)
+            .unwrap()
+            .get_state_mut()
+            .insert(Attribute::Hull, 3);
+
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        assert_eq!(game.outcome(), Some(Outcome::Defeat));
+    }
+
+    #[test]
+    fn test_preview_apply() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 4 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 3,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+        game.hand = game.instances(&[CardId::PHASERS]);
+
+        let effects = &game.card(&CardId::PHASERS).unwrap().effects;
+        let preview = preview_apply(&game, enemy_id, effects);
+
+        // Previewing doesn't change anything
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 10);
+
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        assert_eq!(&preview, game.entity(&enemy_id).unwrap().get_state());
+        assert_eq!(preview.get(&Attribute::Shields), Some(&0));
+        assert_eq!(preview.get(&Attribute::Hull), Some(&9));
+    }
+
+    #[test]
+    fn test_enemy_ability() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 5);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        let template = EnemyTemplate {
+            name: "Gunship",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Defend(1)]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+        game.abilities.push((enemy_id, Box::new(Barrage { amount: 3 })));
+
+        game.actions.push_back(Action::EndTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 2);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 10);
+        assert_eq!(
+            game.events.last(),
+            Some(&GameEvent::AbilityUsed {
+                enemy: enemy_id,
+                ability: String::from("Barrage")
+            })
+        );
+    }
+
+    #[test]
+    fn test_damage_range() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("scatter_shot"),
+            name: "Scatter Shot",
+            effects: vec![Box::new(DamageRange { min: 1, max: 6 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 0,
+        });
+        let mut game = GameState::with_seed(cards, vec![], 7);
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 20,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+
+        let mut hulls = vec![];
+        for _ in 0..3 {
+            game.hand = game.instances(&[CardId::new("scatter_shot")]);
+            game.actions.push_back(Action::PlayCard(enemy_id, 0));
+            tick(&mut game);
+            game.phase = TurnPhase::PlayerInput;
+            hulls.push(game.attribute(&enemy_id, Attribute::Hull));
+        }
+        // Rolls of 6, 4 and 5 for this seed
+        assert_eq!(hulls, vec![14, 10, 5]);
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("reserve_shields"),
+            name: "Reserve Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 4 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill, CardTag::Retain],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+        game.hand = game.instances(&[
+            CardId::PHASERS,
+            CardId::new("reserve_shields"),
+            CardId::SHIELDS,
+            CardId::new("reserve_shields"),
+        ]);
+
+        game.actions.push_back(Action::EndTurn);
+        tick(&mut game);
+
+        // Only the retained cards stay in the hand and they don't charge
+        assert_eq!(
+            game.hand,
+            vec![CardId::new("reserve_shields"), CardId::new("reserve_shields")]
+        );
+        assert!(game.hand.iter().all(|card| card.mods.charge == 0));
+        assert_eq!(game.discard, vec![CardId::PHASERS, CardId::SHIELDS]);
+    }
+
+    #[test]
+    fn test_charge() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("particle_cannon"),
+            name: "Particle Cannon",
+            effects: vec![Box::new(ChargedDamage { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack, CardTag::Charge],
+            cost: 1,
+        });
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let enemy_id = spawn_enemies(&mut game, &[template])[0];
+        game.hand = game.instances(&[CardId::new("particle_cannon"), CardId::PHASERS]);
+
+        // Hold the cannon for two turns
+        for _ in 0..2 {
+            game.actions.push_back(Action::EndTurn);
+            game.actions.push_back(Action::BeginTurn);
+            tick(&mut game);
+        }
+        // The phasers were discarded and drawn again without charging
+        assert_eq!(game.hand, vec![CardId::new("particle_cannon"), CardId::PHASERS]);
+        assert_eq!(game.hand[0].mods.charge, 2);
+        assert_eq!(game.hand[1].mods.charge, 0);
+
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 10 - 1 - 2);
+    }
+
+    #[test]
+    fn test_card_instances_diverge() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![CardId::PHASERS, CardId::PHASERS]);
+        draw_hand(&mut game, 2);
+
+        let (first, second) = (game.hand[0].instance_id, game.hand[1].instance_id);
+        assert_ne!(first, second);
+
+        // Changing one copy leaves the other alone
+        game.hand[0].mods.charge = 3;
+        assert_eq!(game.hand[1].mods.charge, 0);
+
+        // Each copy keeps its identity as it moves between piles
+        game.actions.push_back(Action::DiscardFromHand(1));
+        tick(&mut game);
+        assert_eq!(game.hand[0].instance_id, first);
+        assert_eq!(game.hand[0].mods.charge, 3);
+        assert_eq!(game.discard[0].instance_id, second);
+        assert_eq!(game.deck_composition()[&CardId::PHASERS], 2);
+    }
+
+    #[test]
+    fn test_quick_target() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 3 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 0,
+        });
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let templates = [
+            EnemyTemplate {
+                name: "Frigate",
+                hull: 6,
+                shields: 0,
+                ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(1)]),
+                art: None,
+            },
+            EnemyTemplate {
+                name: "Cruiser",
+                hull: 10,
+                shields: 0,
+                ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(1)]),
+                art: None,
+            },
+        ];
+        let mut game = GameStateBuilder::new(cards, vec![CardId::PHASERS; 4])
+            .player(player)
+            .enemies(&templates)
+            .seed(1)
+            .build();
+        let ids = game.enemy_ids();
+
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.quick_target(0), None);
+
+        game.actions.push_back(Action::PlayCard(ids[0], 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert_eq!(game.last_targeted, Some(ids[0]));
+        assert_eq!(game.quick_target(0), Some(ids[0]));
+
+        // Destroying the enemy forgets it
+        game.actions.push_back(Action::PlayCard(ids[0], 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert!(!game.is_alive(&ids[0]));
+        assert_eq!(game.last_targeted, None);
+        assert_eq!(game.quick_target(0), None);
+    }
+
+    #[test]
+    fn test_rest_heal() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 2);
+        s.insert(Attribute::MaxHull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        game.actions.push_back(Action::RestHeal);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 5);
+
+        // Healing stops at the max hull
+        game.actions.push_back(Action::RestHeal);
+        game.actions.push_back(Action::RestHeal);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 10);
+    }
+
+    #[test]
+    fn test_master_deck() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let deck = vec![CardId::PHASERS, CardId::SHIELDS, CardId::PHASERS];
+        let mut game = GameState::new(cards, deck);
+        game.shuffle = Shuffle::Identity;
+        let kept = game.master_deck[2].instance_id;
+
+        game.actions.push_back(Action::RemoveCard(1));
+        game.actions.push_back(Action::UpgradeCard(1));
+        tick(&mut game);
+        assert_eq!(game.master_deck, vec![CardId::PHASERS, CardId::PHASERS]);
+        assert!(game.master_deck[1].mods.upgraded);
+
+        // The next combat draws from the changed deck and the upgraded
+        // copy costs less
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        start_combat(&mut game, &[template]);
+        tick(&mut game);
+        assert_eq!(game.hand.len(), 2);
+        assert_eq!(game.hand[0].instance_id, kept);
+        assert_eq!(game.card_cost(0), Some(0));
+        assert_eq!(game.card_cost(1), Some(1));
+    }
+
+    #[test]
+    fn test_game_state_builder() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 5 })],
+            target: Target::All,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 4,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(2)]),
+            art: None,
+        };
+        let config = GameConfig {
+            difficulty: Difficulty::Hard,
+            ..Default::default()
+        };
+        let mut game = GameStateBuilder::new(cards, vec![CardId::PHASERS; 8])
+            .player(player)
+            .enemies(&[template.clone(), template])
+            .config(config)
+            .seed(7)
+            .build();
+
+        assert_eq!(game.seed, 7);
+        assert_eq!(game.entity(&game.player).unwrap().name(), "Player");
+        let enemy_ids = game.enemy_ids();
+        assert_eq!(enemy_ids.len(), 2);
+        assert_eq!(game.enemy, Some(enemy_ids[0]));
+        // Enemies are scaled by the configured difficulty
+        assert_eq!(game.attribute(&enemy_ids[0], Attribute::Hull), 6);
+
+        // Fight until the enemies are destroyed
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        game.actions.push_back(Action::EndTurn);
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 10 - 3 - 3);
+        game.phase = TurnPhase::PlayerInput;
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+        assert_eq!(game.outcome(), Some(Outcome::Victory));
+    }
+
+    #[test]
+    fn test_attribute_observer() {
+        use std::cell::Cell;
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 20);
+        s.insert(Attribute::Shields, 5);
+        let player = Player { name: String::from("Player"), state: s };
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(3)]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(CardCollection::new(), vec![])
+            .player(player)
+            .enemies(&[template])
+            .build();
+        let enemy_id = game.enemy.unwrap();
+
+        let fired = Rc::new(Cell::new(0));
+        let counter = fired.clone();
+        game.observe(game.player, Attribute::Shields, Crossing::Falls, 0, move |_| {
+            counter.set(counter.get() + 1);
+        });
+
+        let attack = |game: &mut GameState| {
+            EnemyIntent::Attack(3).resolve(game, enemy_id);
+            game.attribute(&game.player, Attribute::Shields)
+        };
+        assert_eq!(attack(&mut game), 2);
+        assert_eq!(fired.get(), 0);
+        assert_eq!(attack(&mut game), 0);
+        assert_eq!(fired.get(), 1);
+
+        // Staying at the threshold doesn't fire again
+        assert_eq!(attack(&mut game), 0);
+        assert_eq!(fired.get(), 1);
+
+        // Recovering and dropping again does
+        let player_id = game.player;
+        EnemyIntent::Defend(1).resolve(&mut game, player_id);
+        assert_eq!(attack(&mut game), 0);
+        assert_eq!(fired.get(), 2);
+    }
+
+    #[test]
+    fn test_end_combat() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::AUTO_SHIELDS,
+            name: "Auto-Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Power],
+            cost: 0,
+        });
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 6);
+        s.insert(Attribute::MaxHull, 10);
+        s.insert(Attribute::Shields, 2);
+        s.insert(Attribute::Strength, 3);
+        s.insert(Attribute::DeflectorField, 1);
+        let player = Player { name: String::from("Player"), state: s };
+        let deck = vec![CardId::AUTO_SHIELDS, CardId::SHIELDS, CardId::PHASERS, CardId::PHASERS];
+        let mut game = GameStateBuilder::new(cards, deck).player(player).build();
+        let drone = EnemyTemplate {
+            name: "Drone",
+            hull: 3,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        spawn(&mut game, &drone, Team::Player);
+
+        // Play the power and leave cards in every pile
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.hand[3], CardId::AUTO_SHIELDS);
+        game.actions.push_back(Action::PlayCard(game.player, 3));
+        game.actions.push_back(Action::DiscardFromHand(0));
+        tick(&mut game);
+        game.exhaust = game.hand.drain(..1).collect();
+        assert_eq!(game.powers.len(), 1);
+
+        end_combat(&mut game);
+
+        assert!(game.draw.is_empty() && game.hand.is_empty());
+        assert!(game.discard.is_empty() && game.exhaust.is_empty());
+        assert_eq!(game.master_deck.len(), 4);
+        assert!(game.powers.is_empty());
+        assert_eq!(game.energy, 0);
+        assert_eq!(game.turn, 0);
+        assert_eq!(game.entities, vec![game.player]);
+
+        let mut expected = State::new();
+        expected.insert(Attribute::Hull, 6);
+        expected.insert(Attribute::MaxHull, 10);
+        expected.insert(Attribute::Shields, 2);
+        assert_eq!(game.entity(&game.player).unwrap().get_state(), &expected);
+    }
+
+    #[test]
+    fn test_autoplay() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::SHIELDS,
+            name: "Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 3 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 2,
+        });
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(cards, vec![])
+            .player(player)
+            .enemies(&[template])
+            .build();
+        let enemy_id = game.enemy.unwrap();
+        game.hand = game.instances(&[CardId::SHIELDS, CardId::PHASERS, CardId::SHIELDS]);
+
+        // The first affordable card is played first
+        assert_eq!(autoplay_action(&game), Action::PlayCard(game.player, 0));
 
-        // Try with a draw pile of three cards and try to draw four
-        let expected_hand = vec![CardId::Phasers, CardId::Phasers, CardId::Phasers];
-        game.draw = expected_hand.clone();
-        draw_hand(&mut game, 4);
-        assert_eq!(expected_hand, game.hand);
-        assert!(game.draw.is_empty(), "Draw pile should be empty");
+        autoplay_turn(&mut game);
+        assert_eq!(
+            game.history,
+            vec![
+                Action::PlayCard(game.player, 0),
+                Action::PlayCard(enemy_id, 0),
+                Action::EndTurn,
+            ]
+        );
+        assert_eq!(game.energy, 0);
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 1);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 7);
+        assert!(game.hand.is_empty());
+
+        // Nothing is affordable without energy
+        game.hand = game.instances(&[CardId::SHIELDS]);
+        assert_eq!(autoplay_action(&game), Action::EndTurn);
     }
 
     #[test]
-    fn test_discard_hand() {
-        // Initialize game state for the test
-        let cards = CardCollection::new();
-        let init_deck = vec![];
-        let mut game = GameState::new(cards, init_deck);
+    fn test_validate_deck() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+        let game = GameState::new(cards.clone(), vec![CardId::PHASERS, CardId::PHASERS]);
+        assert_eq!(game.validate(), Ok(()));
 
-        // Try with a draw pile of three cards and try to draw four
-        game.hand = vec![CardId::Phasers, CardId::Phasers];
-        discard_hand(&mut game);
-        assert!(game.hand.is_empty(), "Hand should be empty");
-        assert_eq!(
-            vec![CardId::Phasers, CardId::Phasers],
-            game.discard,
-            "Cards from the hand should all be in the discard pile"
-        )
+        // Each missing card is reported once
+        let deck = vec![
+            CardId::new("torpedoes"),
+            CardId::PHASERS,
+            CardId::SHIELDS,
+            CardId::new("torpedoes"),
+        ];
+        let game = GameState::new(cards, deck);
+        assert_eq!(game.validate(), Err(vec![CardId::new("torpedoes"), CardId::SHIELDS]));
     }
 
     #[test]
-    fn test_apply_effects() {
-        // Initialize game state for the test
-        let cards = CardCollection::new();
-        let init_deck = vec![];
-        let mut game = GameState::new(cards, init_deck);
+    fn test_multi_hit() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("rapid_phasers"),
+            name: "Rapid Phasers",
+            effects: vec![Box::new(MultiHit { hits: 3, amount: 2 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 0,
+        });
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 6 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 0,
+        });
 
-        // Add a player entity
+        // Play the card against a frigate with some shields and a
+        // deflector field returning the player's hull afterwards
+        let play = |card_id: CardId| {
+            let mut s = State::new();
+            s.insert(Attribute::Hull, 20);
+            let player = Player { name: String::from("Player"), state: s };
+            let template = EnemyTemplate {
+                name: "Frigate",
+                hull: 10,
+                shields: 3,
+                ai: EnemyAi::Cycle(vec![]),
+                art: None,
+            };
+            let mut game = GameStateBuilder::new(cards.clone(), vec![])
+                .player(player)
+                .enemies(&[template])
+                .build();
+            let enemy_id = game.enemy.unwrap();
+            let mut deflector = State::new();
+            deflector.insert(Attribute::DeflectorField, 1);
+            game.apply_state_change((enemy_id, deflector));
+
+            game.hand = game.instances(&[card_id]);
+            game.actions.push_back(Action::PlayCard(enemy_id, 0));
+            tick(&mut game);
+            let shields = game.attribute(&enemy_id, Attribute::Shields);
+            let hull = game.attribute(&enemy_id, Attribute::Hull);
+            (shields, hull, game.attribute(&game.player, Attribute::Hull))
+        };
+
+        // The shields absorb the first hit and part of the second, the
+        // rest gets through. Each hit triggers the deflector field.
+        assert_eq!(play(CardId::new("rapid_phasers")), (0, 7, 17));
+
+        // One big hit is only retaliated against once
+        assert_eq!(play(CardId::PHASERS), (0, 7, 19));
+    }
+
+    #[test]
+    fn test_echo_next_card() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::new("echo_chamber"),
+            name: "Echo Chamber",
+            effects: vec![Box::new(EchoNextCard)],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 0,
+        });
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![Box::new(DamageHull { amount: 3 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 0,
+        });
         let mut s = State::new();
         s.insert(Attribute::Hull, 10);
-        s.insert(Attribute::Shields, 10);
         let player = Player { name: String::from("Player"), state: s };
-        let player_id = game.add_entity(None, Box::new(player));
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 20,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(cards, vec![])
+            .player(player)
+            .enemies(&[template.clone(), template])
+            .build();
+        let enemy_ids = game.enemy_ids();
+        game.hand = game.instances(&[
+            CardId::new("echo_chamber"),
+            CardId::PHASERS,
+            CardId::PHASERS,
+        ]);
 
-        // We'll test the shields card effects are applied correctly
-        let card = Card {
-            id: CardId::Shields,
+        game.actions.push_back(Action::PlayCard(game.player, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert!(game.duplicate_next);
+
+        // Both resolutions hit the chosen target
+        game.actions.push_back(Action::PlayCard(enemy_ids[1], 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert_eq!(game.attribute(&enemy_ids[1], Attribute::Hull), 14);
+        assert_eq!(game.attribute(&enemy_ids[0], Attribute::Hull), 20);
+        assert!(!game.duplicate_next);
+        assert_eq!(game.discard.len(), 2);
+
+        // Only the next card is echoed
+        game.actions.push_back(Action::PlayCard(enemy_ids[1], 0));
+        tick(&mut game);
+        assert_eq!(game.attribute(&enemy_ids[1], Attribute::Hull), 11);
+    }
+
+    #[test]
+    fn test_shield_regen() {
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 5);
+        s.insert(Attribute::MaxShields, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let config = GameConfig {
+            shield_regen: 3,
+            ..Default::default()
+        };
+        let mut game = GameStateBuilder::new(CardCollection::new(), vec![])
+            .player(player)
+            .config(config)
+            .build();
+
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 8);
+
+        // Regeneration stops at the max shields
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 10);
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 10);
+
+        // Shields raised past the max by cards aren't taken away
+        let mut boost = State::new();
+        boost.insert(Attribute::Shields, 5);
+        game.apply_state_change((game.player, boost));
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 15);
+    }
+
+    #[test]
+    fn test_card_collection_toml() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::PHASERS,
+            name: "Phasers",
+            effects: vec![
+                Box::new(DamageHull { amount: 2 }),
+                Box::new(DamagePerCardPlayed { amount: 1 }),
+                Box::new(Sacrifice {
+                    hull_cost: 3,
+                    amount: 8,
+                    allow_lethal: false,
+                }),
+                Box::new(DamageRange { min: 1, max: 4 }),
+                Box::new(MultiHit { hits: 3, amount: 2 }),
+                Box::new(ChargedDamage { amount: 1 }),
+                Box::new(DamagePiercing { amount: 5 }),
+                Box::new(DamagePercentMaxHull { percent: 20 }),
+                Box::new(ReduceShields { amount: 3 }),
+            ],
+            target: Target::All,
+            tags: vec![CardTag::Attack, CardTag::Charge],
+            cost: 2,
+        });
+        cards.insert(Card {
+            id: CardId::SHIELDS,
             name: "Shields",
-            effects: vec![Box::new(IncreaseShields {})],
+            effects: vec![
+                Box::new(IncreaseShields { amount: 5 }),
+                Box::new(IncreaseBlock { amount: 3 }),
+                Box::new(IncreaseStrength { amount: 1 }),
+                Box::new(DiscountNextCard { amount: 1 }),
+                Box::new(EchoNextCard),
+                Box::new(SalvageDiscard),
+                Box::new(DrawUntil { target_size: 5 }),
+                Box::new(AddCards {
+                    card_id: CardId::new("malware"),
+                    count: 2,
+                    pile: CardPile::Discard,
+                }),
+            ],
             target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+        cards.insert(Card {
+            id: CardId::new("deploy_drone"),
+            name: "Deploy Drone",
+            effects: vec![Box::new(Summon {
+                template: EnemyTemplate {
+                    name: "Drone",
+                    hull: 6,
+                    shields: 0,
+                    ai: EnemyAi::Weighted(vec![
+                        (EnemyIntent::Attack(2), 3),
+                        (EnemyIntent::Defend(1), 1),
+                    ]),
+                    art: Some(" /-\\ \n<[o]>"),
+                },
+            })],
+            target: Target::Ally,
+            tags: vec![],
+            cost: 0,
+        });
+
+        let loaded = CardCollection::from_toml(&cards.to_toml().unwrap()).unwrap();
+
+        assert_eq!(loaded.all_ids().len(), 3);
+        for (id, card) in cards.iter() {
+            let copy = loaded.get(id).expect("Card missing after round trip");
+            assert_eq!(copy.name, card.name);
+            assert_eq!(copy.target, card.target);
+            assert_eq!(copy.tags, card.tags);
+            assert_eq!(copy.cost, card.cost);
+            let descriptors = |card: &Card| -> Vec<EffectDescriptor> {
+                card.effects.iter().map(|e| e.descriptor()).collect()
+            };
+            assert_eq!(descriptors(copy), descriptors(card));
+        }
+    }
+
+    #[test]
+    fn test_damage_range_min_above_max() {
+        let toml = r#"
+            [[cards]]
+            id = "phasers"
+            name = "Phasers"
+            target = "Single"
+            cost = 1
+
+            [[cards.effects]]
+            effect = "DamageRange"
+            min = 6
+            max = 1
+        "#;
+        let err = CardCollection::from_toml(toml).expect_err("Range should be rejected");
+        assert!(err.to_string().contains("min 6 is above max 1"), "{}", err);
+
+        let valid = toml.replace("min = 6", "min = 1").replace("max = 1", "max = 6");
+        assert!(CardCollection::from_toml(&valid).is_ok());
+    }
+
+    #[test]
+    fn test_enemy_turn_order() {
+        let template = |name: &'static str, attack: i32| EnemyTemplate {
+            name,
+            hull: 5,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(attack)]),
+            art: None,
         };
+        let mut game = GameStateBuilder::new(CardCollection::new(), vec![])
+            .enemies(&[template("Frigate", 1), template("Drone", 2), template("Cruiser", 3)])
+            .build();
+        let ids = game.enemy_ids();
 
-        // Apply state change for the card
-        let state_change = card.effects[0].calculate(&game, player_id);
-        game.apply_effect((player_id, state_change));
+        assert_eq!(game.enemy_turn_order(), ids);
+
+        // Destroyed enemies drop out without changing the others' order
+        let mut destroyed = State::new();
+        destroyed.insert(Attribute::Hull, -5);
+        game.apply_state_change((ids[1], destroyed));
+        assert_eq!(game.enemy_turn_order(), vec![ids[0], ids[2]]);
+
+        // Intents resolve in the same order
+        game.events.clear();
+        game.actions.push_back(Action::EndTurn);
+        tick(&mut game);
+        let acted: Vec<EntityId> = game
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                GameEvent::EnemyActed { enemy, .. } => Some(*enemy),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(acted, game.enemy_turn_order());
+    }
+
+    #[test]
+    fn test_missing_entities() {
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let frigate = EnemyTemplate {
+            name: "Frigate",
+            hull: 5,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(CardCollection::new(), vec![])
+            .player(player)
+            .enemies(&[frigate])
+            .build();
+        assert_eq!(game.player_state().map(|s| s[&Attribute::Hull]), Some(10));
+        assert_eq!(game.enemy_ids().len(), 1);
+
+        let player_id = game.player;
+        game.remove_entity(&player_id).unwrap();
+        let enemy_id = game.enemy.unwrap();
+        game.remove_entity(&enemy_id).unwrap();
+
+        assert_eq!(game.player_state(), None);
+        assert!(game.enemy_ids().is_empty());
+        assert_eq!(game.enemy, None);
+    }
+
+    #[test]
+    fn test_run_encounters() {
+        let enemy = |name: &'static str, hull: i32| EnemyTemplate {
+            name,
+            hull,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(1)]),
+            art: None,
+        };
+        let mut run = Run::new(vec![
+            Encounter {
+                enemies: vec![enemy("Frigate", 5)],
+            },
+            Encounter {
+                enemies: vec![enemy("Drone", 2), enemy("Drone", 2)],
+            },
+        ]);
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let mut game = GameStateBuilder::new(CardCollection::new(), vec![CardId::SHIELDS; 3])
+            .player(player)
+            .build();
+
+        run.start_encounter(&mut game);
+        tick(&mut game);
+        let names = |game: &GameState| -> Vec<String> {
+            game.enemy_ids()
+                .iter()
+                .map(|id| game.entity(id).unwrap().name().to_string())
+                .collect()
+        };
+        assert_eq!(names(&game), vec!["Frigate"]);
+
+        // Win the first encounter after taking some damage
+        let mut hit = State::new();
+        hit.insert(Attribute::Hull, -4);
+        game.apply_state_change((game.player, hit));
+        let frigate = game.enemy.unwrap();
+        game.remove_entity(&frigate).unwrap();
+        assert_eq!(game.outcome(), Some(Outcome::Victory));
+
+        assert!(run.advance(&mut game).is_some());
+        run.start_encounter(&mut game);
+        tick(&mut game);
+        assert_eq!(run.current_encounter, 1);
+        assert_eq!(names(&game), vec!["Drone", "Drone"]);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 6);
+        assert_eq!(game.hand.len() + game.draw.len(), 3);
+
+        // Winning the last encounter completes the run
+        for id in game.enemy_ids() {
+            game.remove_entity(&id).unwrap();
+        }
+        assert!(run.advance(&mut game).is_none());
+        assert!(run.is_complete());
+    }
+
+    #[test]
+    fn test_escalating_intent() {
+        let escalate = EnemyIntent::Escalate {
+            base: 2,
+            per_turn: 1,
+        };
+        assert_eq!(escalate.for_turn(1), EnemyIntent::Attack(3));
+        assert_eq!(escalate.for_turn(5), EnemyIntent::Attack(7));
+        assert_eq!(EnemyIntent::Defend(2).for_turn(5), EnemyIntent::Defend(2));
+
+        let attacks_at = |turn: u32| -> (i32, Vec<GameEvent>) {
+            let mut s = State::new();
+            s.insert(Attribute::Hull, 20);
+            let player = Player { name: String::from("Player"), state: s };
+            let boss = EnemyTemplate {
+                name: "Dreadnought",
+                hull: 30,
+                shields: 0,
+                ai: EnemyAi::Cycle(vec![escalate]),
+                art: None,
+            };
+            let mut game = GameStateBuilder::new(CardCollection::new(), vec![])
+                .player(player)
+                .enemies(&[boss])
+                .build();
+            game.turn = turn;
+            game.events.clear();
+            game.actions.push_back(Action::EndTurn);
+            tick(&mut game);
+            (game.attribute(&game.player, Attribute::Hull), game.events)
+        };
+
+        // The event records the attack made that turn
+        let (hull, events) = attacks_at(1);
+        assert_eq!(hull, 17);
+        assert!(events.iter().any(|event| matches!(
+            event,
+            GameEvent::EnemyActed {
+                intent: EnemyIntent::Attack(3),
+                ..
+            }
+        )));
+        assert_eq!(attacks_at(5).0, 13);
+    }
 
+    #[test]
+    fn test_block_expires() {
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 20);
+        s.insert(Attribute::Shields, 3);
+        let player = Player { name: String::from("Player"), state: s };
+        let frigate = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(4)]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(CardCollection::new(), vec![])
+            .player(player)
+            .enemies(&[frigate])
+            .build();
+        let mut block = State::new();
+        block.insert(Attribute::Block, 7);
+        game.apply_state_change((game.player, block));
+
+        // Block absorbs the attack before the shields
+        game.actions.push_back(Action::EndTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Block), 3);
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 3);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 20);
+
+        // The rest of the block is lost when the turn starts but the
+        // shields carry over
+        game.events.clear();
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Block), 0);
+        assert_eq!(game.attribute(&game.player, Attribute::Shields), 3);
         assert_eq!(
-            game.entity_state
-                .get_mut(&player_id)
-                .unwrap()
-                .get_state()
-                .get(&Attribute::Shields)
-                .unwrap(),
-            &11i32
-        )
+            game.events,
+            vec![GameEvent::BlockExpired {
+                entity: game.player,
+                amount: 3
+            }]
+        );
+
+        // Nothing is reported without block to lose
+        game.events.clear();
+        game.actions.push_back(Action::BeginTurn);
+        tick(&mut game);
+        assert!(game.events.is_empty());
     }
 
     #[test]
-    fn test_integration() {
-        let mut cards = CardCollection::new();
+    fn test_forecast_intents() {
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 50);
+        let player = Player { name: String::from("Player"), state: s };
+        let enemy = |name: &'static str| EnemyTemplate {
+            name,
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Weighted(vec![
+                (EnemyIntent::Attack(1), 1),
+                (EnemyIntent::Attack(2), 1),
+                (EnemyIntent::Defend(3), 1),
+            ]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(CardCollection::new(), vec![])
+            .player(player)
+            .enemies(&[enemy("Frigate"), enemy("Cruiser")])
+            .seed(11)
+            .build();
+
+        let forecast = game.forecast_intents(2);
+        // Forecasting doesn't change the game
+        assert_eq!(game.forecast_intents(2), forecast);
+
+        for turn in 0..2 {
+            game.events.clear();
+            game.actions.push_back(Action::EndTurn);
+            game.actions.push_back(Action::BeginTurn);
+            tick(&mut game);
+            let acted: Vec<(EntityId, EnemyIntent)> = game
+                .events
+                .iter()
+                .filter_map(|event| match event {
+                    GameEvent::EnemyActed { enemy, intent } => Some((*enemy, *intent)),
+                    _ => None,
+                })
+                .collect();
+            let expected: Vec<(EntityId, EnemyIntent)> = forecast
+                .iter()
+                .map(|(enemy, intents)| (*enemy, intents[turn]))
+                .collect();
+            assert_eq!(acted, expected);
+        }
+    }
+
+    #[test]
+    fn test_damage_percent_max_hull() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        let target = |game: &mut GameState, hull: i32, max_hull: i32| {
+            let mut s = State::new();
+            s.insert(Attribute::Hull, hull);
+            s.insert(Attribute::MaxHull, max_hull);
+            game.add_entity(None, Box::new(Player { name: String::from("Target"), state: s }))
+        };
+        let effect = DamagePercentMaxHull { percent: 20 };
+
+        // 20% of 9 is 1.8 which rounds down
+        let damaged = target(&mut game, 4, 9);
+        assert_eq!(effect.calculate(&game, game.player, damaged)[&Attribute::Hull], -1);
+
+        // Damage comes from the max hull rather than what's left
+        let full = target(&mut game, 50, 50);
+        let hurt = target(&mut game, 10, 50);
+        assert_eq!(effect.calculate(&game, game.player, full)[&Attribute::Hull], -10);
+        assert_eq!(effect.calculate(&game, game.player, hurt)[&Attribute::Hull], -10);
+
+        // Targets without a max hull take no damage
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let unknown = game.add_entity(None, Box::new(Player { name: String::from("?"), state: s }));
+        assert_eq!(effect.calculate(&game, game.player, unknown)[&Attribute::Hull], 0);
+    }
+
+    #[test]
+    fn test_overdraw_burned() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        game.hand = game.instances(&[CardId::SHIELDS; MAX_HAND_SIZE - 1]);
+        game.draw = game.instances(&[CardId::PHASERS; 3]);
+
+        // Only one card fits and the rest are discarded
+        draw_hand(&mut game, 3);
+        assert_eq!(game.hand.len(), MAX_HAND_SIZE);
+        assert_eq!(game.discard.len(), 2);
+        assert!(game.draw.is_empty());
+        assert_eq!(
+            game.events,
+            vec![
+                GameEvent::OverdrawBurned { card: CardId::PHASERS },
+                GameEvent::OverdrawBurned { card: CardId::PHASERS },
+            ]
+        );
+
+        // Or exhausted if the config says so
+        game.config.exhaust_overdraw = true;
+        game.events.clear();
+        game.draw = game.instances(&[CardId::PHASERS]);
+        draw_hand(&mut game, 1);
+        assert_eq!(game.discard.len(), 2);
+        assert_eq!(game.exhaust.len(), 1);
+        assert_eq!(game.events, vec![GameEvent::OverdrawBurned { card: CardId::PHASERS }]);
+    }
+
+    #[test]
+    fn test_reduce_shields() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::Shields, 2);
+        let player = Player { name: String::from("Target"), state: s };
+        let target = game.add_entity(None, Box::new(player));
+
+        // Draining more than the target has stops at zero
+        let state_change = ReduceShields { amount: 5 }.calculate(&game, game.player, target);
+        assert_eq!(state_change[&Attribute::Shields], -2);
+        assert!(!state_change.contains_key(&Attribute::Hull));
+
+        game.apply_state_change((target, state_change));
+        assert_eq!(game.attribute(&target, Attribute::Shields), 0);
+        assert_eq!(game.attribute(&target, Attribute::Hull), 10);
 
+        // Nothing left to drain
+        let state_change = ReduceShields { amount: 5 }.calculate(&game, game.player, target);
+        assert_eq!(state_change[&Attribute::Shields], 0);
+    }
+
+    #[test]
+    fn test_unplayable_cards() {
+        let mut cards = CardCollection::new();
+        let malware = CardId::new("malware");
         cards.insert(Card {
-            id: CardId::Shields,
-            name: "Shields",
-            effects: vec![Box::new(IncreaseShields {})],
-            target: Target::Player,
+            id: malware,
+            name: "Malware",
+            effects: vec![Box::new(DamageHull { amount: 5 })],
+            target: Target::Single,
+            tags: vec![CardTag::Unplayable],
+            cost: 0,
         });
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(cards, vec![])
+            .player(player)
+            .enemies(&[template])
+            .build();
+        game.shuffle = Shuffle::Identity;
+        let enemy_id = game.enemy.unwrap();
+
+        // Cards can be added to either pile
+        add_cards(&mut game, malware, 1, CardPile::Discard);
+        let player_id = game.player;
+        let effect = AddCards { card_id: malware, count: 2, pile: CardPile::Draw };
+        effect.resolve(&mut game, player_id, player_id);
+        assert_eq!(game.discard.len(), 1);
+        assert_eq!(game.draw.len(), 2);
+        assert!(game.draw.iter().all(|card| card.card_id == malware));
+
+        // Playing it does nothing and leaves it in the hand
+        draw_hand(&mut game, 1);
+        assert!(!game.can_play(0));
+        assert_eq!(game.legal_actions(), vec![Action::EndTurn]);
+        let energy = game.energy;
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        assert_eq!(game.hand.len(), 1);
+        assert_eq!(game.energy, energy);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 10);
+    }
+
+    #[test]
+    fn test_end_turn_discard_modes() {
+        let end_turn = |end_turn_discard: EndTurnDiscard| {
+            let mut game = GameState::new(CardCollection::new(), vec![]);
+            game.config.end_turn_discard = end_turn_discard;
+            game.hand = game.instances(&[CardId::PHASERS, CardId::SHIELDS]);
+            game.actions.push_back(Action::EndTurn);
+            tick(&mut game);
+            game
+        };
+
+        let game = end_turn(EndTurnDiscard::DiscardHand);
+        assert!(game.hand.is_empty());
+        assert_eq!(game.discard, vec![CardId::PHASERS, CardId::SHIELDS]);
+
+        let game = end_turn(EndTurnDiscard::KeepHand);
+        assert_eq!(game.hand, vec![CardId::PHASERS, CardId::SHIELDS]);
+        assert!(game.discard.is_empty());
+    }
 
+    #[test]
+    fn test_undo_card() {
+        let mut cards = CardCollection::new();
         cards.insert(Card {
-            id: CardId::Phasers,
+            id: CardId::PHASERS,
             name: "Phasers",
-            effects: vec![Box::new(DamageHull {})],
+            effects: vec![Box::new(DamageHull { amount: 3 })],
             target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
         });
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(cards, vec![])
+            .player(player)
+            .enemies(&[template])
+            .build();
+        let enemy_id = game.enemy.unwrap();
+        game.hand = game.instances(&[CardId::PHASERS, CardId::PHASERS]);
+        let before = game.clone();
 
-        let mut init_deck = vec![
-            CardId::Shields,
-            CardId::Shields,
-            CardId::Shields,
-            CardId::Phasers,
-            CardId::Phasers,
-            CardId::Phasers,
-        ];
-        shuffle_deck(&mut init_deck);
+        // Nothing to undo yet
+        game.actions.push_back(Action::UndoCard);
+        tick(&mut game);
+        assert_eq!(game.hand, before.hand);
 
-        let mut game = GameState::new(cards, init_deck);
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 7);
+        assert_eq!(game.hand.len(), 1);
 
-        // Add an enemy
+        game.phase = TurnPhase::PlayerInput;
+        game.actions.push_back(Action::UndoCard);
+        tick(&mut game);
+        assert_eq!(game.hand, before.hand);
+        assert_eq!(game.hand[0].instance_id, before.hand[0].instance_id);
+        assert_eq!(game.discard, before.discard);
+        assert_eq!(game.energy, before.energy);
+        assert_eq!(game.attribute(&enemy_id, Attribute::Hull), 10);
+        assert_eq!(game.events, before.events);
+        assert_eq!(game.phase, TurnPhase::PlayerInput);
+        assert!(game.undo.is_none());
+
+        // The undo stays in the history for replays
+        assert_eq!(
+            game.history,
+            vec![Action::UndoCard, Action::PlayCard(enemy_id, 0), Action::UndoCard]
+        );
+
+        // Plays can't be undone once the turn ends
+        game.actions.push_back(Action::PlayCard(enemy_id, 0));
+        tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        game.actions.push_back(Action::EndTurn);
+        tick(&mut game);
+        assert!(game.undo.is_none());
+    }
+
+    #[test]
+    fn test_undo_cleared_by_other_actions() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::SHIELDS,
+            name: "Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
         let mut s = State::new();
         s.insert(Attribute::Hull, 10);
-        s.insert(Attribute::Shields, 10);
-        let enemy = Enemy { name: String::from("Test Enemy"), state: s };
-        let enemy_id = game.add_entity(None, Box::new(enemy));
+        let player = Player { name: String::from("Player"), state: s };
+        let mut game = GameStateBuilder::new(cards, vec![]).player(player).build();
+        let player_id = game.player;
+        game.hand = game.instances(&[CardId::SHIELDS]);
+        game.draw = game.instances(&[CardId::PHASERS, CardId::PHASERS]);
 
-        // Run through a turn to make sure it works
-        game.action = Action::BeginTurn;
+        game.actions.push_back(Action::PlayCard(player_id, 0));
         tick(&mut game);
+        game.phase = TurnPhase::PlayerInput;
+        assert!(game.undo.is_some());
 
-        game.action = Action::PlayCard(enemy_id, 0);
+        // Discarding a scried card can't be taken back by undoing the
+        // card played before it
+        game.actions.push_back(Action::Scry(1));
+        game.actions.push_back(Action::ScryDiscard(0));
         tick(&mut game);
+        assert!(game.undo.is_none());
 
-        game.action = Action::PlayCard(enemy_id, 0);
+        game.actions.push_back(Action::UndoCard);
         tick(&mut game);
+        assert!(game.hand.is_empty(), "Card should stay played");
+        assert_eq!(game.discard, vec![CardId::SHIELDS, CardId::PHASERS]);
+    }
 
-        game.action = Action::EndTurn;
-        tick(&mut game);
+    #[test]
+    fn test_block_dodge_and_deflect() {
+        let mut game = GameState::with_seed(CardCollection::new(), vec![], 1);
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        game.player = game.add_entity(None, Box::new(Player { name: String::from("P"), state: s }));
+
+        // Dodging keeps the block that would have absorbed the hit
+        let target = evasive_target(&mut game, 100);
+        let mut block = State::new();
+        block.insert(Attribute::Block, 5);
+        game.apply_state_change((target, block.clone()));
+        let state_change = damage(game.entity(&target).unwrap().get_state(), 3);
+        game.apply_effect(game.player, (target, state_change));
+        assert_eq!(game.attribute(&target, Attribute::Block), 5);
+        assert_eq!(game.attribute(&target, Attribute::Hull), 100);
+
+        // A hit absorbed by block still sets off the deflector field
+        let target = evasive_target(&mut game, 0);
+        block.insert(Attribute::DeflectorField, 2);
+        game.apply_state_change((target, block));
+        let state_change = damage(game.entity(&target).unwrap().get_state(), 3);
+        game.apply_effect(game.player, (target, state_change));
+        assert_eq!(game.attribute(&target, Attribute::Block), 2);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 8);
+    }
+
+    #[test]
+    fn test_caster_attributes() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        let entity = |game: &mut GameState, hull: i32, strength: i32, dexterity: i32| {
+            let mut s = State::new();
+            s.insert(Attribute::Hull, hull);
+            s.insert(Attribute::Strength, strength);
+            s.insert(Attribute::Dexterity, dexterity);
+            game.add_entity(None, Box::new(Player { name: String::from("Ship"), state: s }))
+        };
+        game.player = entity(&mut game, 10, 0, 0);
+        let ally = entity(&mut game, 2, 3, 1);
+        let target = entity(&mut game, 20, 0, 0);
+
+        // The caster's strength and dexterity are used, not the player's
+        let state_change = DamageHull { amount: 2 }.calculate(&game, ally, target);
+        assert_eq!(state_change[&Attribute::Hull], -5);
+        let state_change = DamagePiercing { amount: 2 }.calculate(&game, game.player, target);
+        assert_eq!(state_change[&Attribute::Hull], -2);
+        let state_change = IncreaseShields { amount: 2 }.calculate(&game, ally, ally);
+        assert_eq!(state_change[&Attribute::Shields], 3);
+
+        // And so is their hull when paying a cost
+        let sacrifice = Sacrifice { hull_cost: 3, amount: 5, allow_lethal: false };
+        assert!(sacrifice.can_pay(&game, game.player));
+        assert!(!sacrifice.can_pay(&game, ally));
     }
 }
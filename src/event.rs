@@ -1,43 +1,82 @@
 #![allow(dead_code)]
 
 /// Copied from tui-rs example utils
+use std::collections::VecDeque;
+#[cfg(feature = "termion-backend")]
 use std::io;
 use std::sync::mpsc;
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
 use std::thread;
 use std::time::Duration;
 
-use termion::event::Key;
+#[cfg(feature = "termion-backend")]
 use termion::input::TermRead;
 
+use crate::keys::Key;
+
 pub enum Event<I> {
     Input(I),
     Tick,
 }
 
+#[cfg(feature = "termion-backend")]
+impl From<termion::event::Key> for Key {
+    fn from(key: termion::event::Key) -> Key {
+        use termion::event::Key as TermKey;
+        match key {
+            TermKey::Backspace => Key::Backspace,
+            TermKey::Left => Key::Left,
+            TermKey::Right => Key::Right,
+            TermKey::Up => Key::Up,
+            TermKey::Down => Key::Down,
+            TermKey::PageUp => Key::PageUp,
+            TermKey::PageDown => Key::PageDown,
+            TermKey::Esc => Key::Esc,
+            TermKey::Char(c) => Key::Char(c),
+            _ => Key::Other,
+        }
+    }
+}
+
 /// A small event handler that wrap termion input and tick events. Each event
 /// type is handled in its own thread and returned to a common `Receiver`
 pub struct Events {
     rx: mpsc::Receiver<Event<Key>>,
+    #[cfg(feature = "termion-backend")]
     input_handle: thread::JoinHandle<()>,
-    ignore_exit_key: Arc<AtomicBool>,
     tick_handle: thread::JoinHandle<()>,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Time between ticks unless configured otherwise
+pub const DEFAULT_TICK_RATE: Duration = Duration::from_millis(250);
+
+/// Shortest time between ticks. Anything faster would busy-spin the
+/// tick thread.
+pub const MIN_TICK_RATE: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Config {
-    pub exit_key: Key,
     pub tick_rate: Duration,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
-            exit_key: Key::Char('q'),
-            tick_rate: Duration::from_millis(250),
+            tick_rate: DEFAULT_TICK_RATE,
+        }
+    }
+}
+
+impl Config {
+    /// The default config ticking at `tick_rate` clamped to
+    /// `MIN_TICK_RATE`
+    pub fn with_tick_rate(tick_rate: Duration) -> Config {
+        Config { tick_rate }.validated()
+    }
+
+    /// The config with any invalid values replaced
+    pub fn validated(self) -> Config {
+        Config {
+            tick_rate: self.tick_rate.max(MIN_TICK_RATE),
         }
     }
 }
@@ -48,22 +87,20 @@ impl Events {
     }
 
     pub fn with_config(config: Config) -> Events {
+        let config = config.validated();
         let (tx, rx) = mpsc::channel();
-        let ignore_exit_key = Arc::new(AtomicBool::new(false));
+        // Keys are forwarded until the game stops listening. Quitting
+        // is up to the game loop so it can tell when a key is typed
+        // rather than a binding.
+        #[cfg(feature = "termion-backend")]
         let input_handle = {
             let tx = tx.clone();
-            let ignore_exit_key = ignore_exit_key.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    if let Ok(key) = evt {
-                        if let Err(err) = tx.send(Event::Input(key)) {
-                            eprintln!("{}", err);
-                            return;
-                        }
-                        if !ignore_exit_key.load(Ordering::Relaxed) && key == config.exit_key {
-                            return;
-                        }
+                for key in stdin.keys().flatten() {
+                    if let Err(err) = tx.send(Event::Input(key.into())) {
+                        eprintln!("{}", err);
+                        return;
                     }
                 }
             })
@@ -78,7 +115,7 @@ impl Events {
         };
         Events {
             rx,
-            ignore_exit_key,
+            #[cfg(feature = "termion-backend")]
             input_handle,
             tick_handle,
         }
@@ -87,12 +124,148 @@ impl Events {
     pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
         self.rx.recv()
     }
+}
+
+/// Wall clock time spent in combat for speedruns. Time is added one
+/// tick at a time while the clock is running.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Clock {
+    pub elapsed: Duration,
+    running: bool,
+}
+
+impl Clock {
+    pub fn start(&mut self) {
+        self.running = true;
+    }
+
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    /// Add the time since the last tick if the clock is running
+    pub fn tick(&mut self, tick_rate: Duration) {
+        if self.running {
+            self.elapsed += tick_rate;
+        }
+    }
+}
+
+/// Holds key presses until the game is ready for them so keys
+/// pressed while a tick is still resolving aren't dropped
+#[derive(Debug)]
+pub struct InputBuffer<I> {
+    inputs: VecDeque<I>,
+}
+
+impl<I> Default for InputBuffer<I> {
+    fn default() -> Self {
+        InputBuffer {
+            inputs: VecDeque::new(),
+        }
+    }
+}
+
+impl<I> InputBuffer<I> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, input: I) {
+        self.inputs.push_back(input);
+    }
+
+    /// The oldest buffered input if the game is `ready` for it
+    pub fn pop_if(&mut self, ready: bool) -> Option<I> {
+        if ready {
+            self.inputs.pop_front()
+        } else {
+            None
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test_events {
+    use super::*;
+
+    #[test]
+    fn test_zero_tick_rate_is_clamped() {
+        let config = Config::with_tick_rate(Duration::ZERO);
+        assert_eq!(config.tick_rate, MIN_TICK_RATE);
+
+        let config = Config {
+            tick_rate: Duration::ZERO,
+        };
+        assert_eq!(config.validated().tick_rate, MIN_TICK_RATE);
+
+        // Valid tick rates are left alone
+        let config = Config::with_tick_rate(DEFAULT_TICK_RATE);
+        assert_eq!(config.tick_rate, DEFAULT_TICK_RATE);
+    }
+
+    #[test]
+    fn test_input_buffer() {
+        let mut buffer = InputBuffer::new();
+        buffer.push(Key::Char('1'));
+        buffer.push(Key::Char('2'));
+        buffer.push(Key::Char('e'));
+
+        // Nothing is taken until the game is ready
+        assert_eq!(buffer.pop_if(false), None);
+        assert_eq!(buffer.len(), 3);
 
-    pub fn disable_exit_key(&mut self) {
-        self.ignore_exit_key.store(true, Ordering::Relaxed);
+        // Inputs come out in the order they were pressed
+        assert_eq!(buffer.pop_if(true), Some(Key::Char('1')));
+        assert_eq!(buffer.pop_if(false), None);
+        assert_eq!(buffer.pop_if(true), Some(Key::Char('2')));
+        assert_eq!(buffer.pop_if(true), Some(Key::Char('e')));
+        assert_eq!(buffer.pop_if(true), None);
+        assert!(buffer.is_empty());
     }
 
-    pub fn enable_exit_key(&mut self) {
-        self.ignore_exit_key.store(false, Ordering::Relaxed);
+    #[test]
+    fn test_clock() {
+        let tick_rate = Duration::from_millis(250);
+        let mut clock = Clock::default();
+
+        // Nothing is counted until the clock starts
+        clock.tick(tick_rate);
+        assert_eq!(clock.elapsed, Duration::ZERO);
+
+        clock.start();
+        for _ in 0..10 {
+            clock.tick(tick_rate);
+        }
+        assert_eq!(clock.elapsed, Duration::from_millis(2500));
+
+        clock.stop();
+        clock.tick(tick_rate);
+        assert_eq!(clock.elapsed, Duration::from_millis(2500));
+        assert!(!clock.is_running());
+    }
+
+    #[cfg(feature = "termion-backend")]
+    #[test]
+    fn test_termion_keys() {
+        use termion::event::Key as TermKey;
+
+        assert_eq!(Key::from(TermKey::Char('e')), Key::Char('e'));
+        assert_eq!(Key::from(TermKey::Esc), Key::Esc);
+        assert_eq!(Key::from(TermKey::PageDown), Key::PageDown);
+        // Keys the game doesn't use aren't kept
+        assert_eq!(Key::from(TermKey::F(1)), Key::Other);
     }
 }
@@ -4,13 +4,115 @@
 //!
 //! See [this blog post](https://hoverbear.org/blog/rust-state-machine-pattern/)
 //! for more about this design
-use crate::engine::EntityId;
+#![allow(dead_code)]
+
+use std::cmp::Ordering;
+
+use crate::engine::{Card, CardCollection, CardInstance, EntityId, GameState, Outcome};
 
 /// A collection of shared state between different transitions. Useful
 /// so you don't need to duplicate the same attributes across multiple
 /// states.
 pub struct SharedState {}
 
+/// Panels that can be scrolled when they hold more than fits on
+/// screen
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Panel {
+    Log,
+    Discard,
+}
+
+impl Panel {
+    pub fn next(self) -> Self {
+        match self {
+            Panel::Log => Panel::Discard,
+            Panel::Discard => Panel::Log,
+        }
+    }
+}
+
+/// Piles of cards that can be viewed in full
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum PileKind {
+    Draw,
+    Discard,
+    Exhaust,
+}
+
+impl PileKind {
+    pub fn next(self) -> Self {
+        match self {
+            PileKind::Draw => PileKind::Discard,
+            PileKind::Discard => PileKind::Exhaust,
+            PileKind::Exhaust => PileKind::Draw,
+        }
+    }
+}
+
+/// The order cards in the hand are shown in. Number keys play the
+/// card at the position shown rather than its position in the hand.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum HandOrder {
+    /// The order the cards were drawn
+    Draw,
+    Cost,
+    Name,
+    /// Grouped by the card's first tag
+    Type,
+}
+
+impl HandOrder {
+    pub fn next(self) -> Self {
+        match self {
+            HandOrder::Draw => HandOrder::Cost,
+            HandOrder::Cost => HandOrder::Name,
+            HandOrder::Name => HandOrder::Type,
+            HandOrder::Type => HandOrder::Draw,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            HandOrder::Draw => "drawn",
+            HandOrder::Cost => "cost",
+            HandOrder::Name => "name",
+            HandOrder::Type => "type",
+        }
+    }
+
+    pub fn compare(self, a: &Card, b: &Card) -> Ordering {
+        match self {
+            HandOrder::Draw => Ordering::Equal,
+            HandOrder::Cost => a.cost.cmp(&b.cost),
+            HandOrder::Name => a.name.cmp(b.name),
+            HandOrder::Type => a.tags.first().cmp(&b.tags.first()),
+        }
+    }
+
+    /// Indices into the hand in the order they're shown. Ties keep
+    /// the order they were drawn.
+    pub fn hand_indices(self, game_state: &GameState) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..game_state.hand.len()).collect();
+        indices.sort_by(|a, b| {
+            let card_a = game_state.cards.get(&game_state.hand[*a].card_id);
+            let card_b = game_state.cards.get(&game_state.hand[*b].card_id);
+            match (card_a, card_b) {
+                (Some(card_a), Some(card_b)) => self.compare(card_a, card_b),
+                _ => Ordering::Equal,
+            }
+        });
+        indices
+    }
+}
+
+/// Move a scroll `offset` by `delta` items without scrolling past
+/// either end of a list of `len` items
+pub fn scroll(offset: usize, delta: i32, len: usize) -> usize {
+    let max_offset = len.saturating_sub(1) as i64;
+    (offset as i64 + delta as i64).max(0).min(max_offset) as usize
+}
+
 pub struct GuiStateMachine<T> {
     pub state: T,
 }
@@ -27,22 +129,20 @@ pub trait GuiState {
 
 pub struct Combat {
     pub shared_state: SharedState,
-    pub enemy_id: EntityId,
 }
 
 impl Combat {
-    pub fn new(enemy_id: EntityId) -> Self {
+    pub fn new() -> Self {
         Combat {
             shared_state: SharedState {},
-            enemy_id,
         }
     }
 }
 
 impl GuiStateMachine<Combat> {
-    pub fn new(enemy_id: EntityId) -> Self {
+    pub fn new() -> Self {
         GuiStateMachine {
-            state: Combat::new(enemy_id)
+            state: Combat::new()
         }
     }
 }
@@ -76,6 +176,24 @@ pub struct TargetSelect {
     pub shared_state: SharedState,
     pub targets: Vec<EntityId>,
     pub card_idx: u32,
+    /// Index of the highlighted target in `targets`
+    pub selected_target_idx: usize,
+}
+
+impl TargetSelect {
+    /// Move the highlight by `delta` targets wrapping around either
+    /// end of the list
+    pub fn move_cursor(&mut self, delta: i32) {
+        let len = self.targets.len() as i32;
+        if len > 0 {
+            let idx = self.selected_target_idx as i32 + delta;
+            self.selected_target_idx = idx.rem_euclid(len) as usize;
+        }
+    }
+
+    pub fn selected_target(&self) -> Option<EntityId> {
+        self.targets.get(self.selected_target_idx).copied()
+    }
 }
 
 pub struct TargetSelectArgs {
@@ -95,6 +213,7 @@ impl TransitionFrom<&GuiStateMachine<PlayCard>> for GuiStateMachine<TargetSelect
                 shared_state: SharedState {},
                 targets: args.targets,
                 card_idx: args.card_idx,
+                selected_target_idx: 0,
             },
         }
     }
@@ -128,6 +247,153 @@ impl TransitionFrom<&GuiStateMachine<TargetSelect>> for GuiStateMachine<TargetSe
     }
 }
 
+/// Looking at cards revealed from the top of the draw pile
+pub struct Scry {
+    pub shared_state: SharedState,
+}
+
+impl TransitionFrom<&GuiStateMachine<Combat>> for GuiStateMachine<Scry> {
+    type Args = ();
+
+    fn transition_from(_fsm: &GuiStateMachine<Combat>, _args: ()) -> GuiStateMachine<Scry> {
+        GuiStateMachine {
+            state: Scry {
+                shared_state: SharedState {},
+            },
+        }
+    }
+}
+
+/// Choosing a card from the discard pile to return to the hand
+pub struct Salvage {
+    pub shared_state: SharedState,
+}
+
+impl TransitionFrom<&GuiStateMachine<Combat>> for GuiStateMachine<Salvage> {
+    type Args = ();
+
+    fn transition_from(_fsm: &GuiStateMachine<Combat>, _args: ()) -> GuiStateMachine<Salvage> {
+        GuiStateMachine {
+            state: Salvage {
+                shared_state: SharedState {},
+            },
+        }
+    }
+}
+
+/// Asking whether to end the turn with cards still playable
+pub struct ConfirmEndTurn {
+    pub shared_state: SharedState,
+}
+
+impl TransitionFrom<&GuiStateMachine<Combat>> for GuiStateMachine<ConfirmEndTurn> {
+    type Args = ();
+
+    fn transition_from(
+        _fsm: &GuiStateMachine<Combat>,
+        _args: (),
+    ) -> GuiStateMachine<ConfirmEndTurn> {
+        GuiStateMachine {
+            state: ConfirmEndTurn {
+                shared_state: SharedState {},
+            },
+        }
+    }
+}
+
+/// Looking through the cards in one of the piles
+pub struct ViewPile {
+    pub shared_state: SharedState,
+    pub pile: PileKind,
+    /// Only cards with names containing the query are shown
+    pub query: String,
+    /// Keys are typed into the query rather than controlling the
+    /// modal
+    pub typing: bool,
+}
+
+impl GuiStateMachine<ViewPile> {
+    pub fn new(pile: PileKind) -> Self {
+        GuiStateMachine {
+            state: ViewPile {
+                shared_state: SharedState {},
+                pile,
+                query: String::new(),
+                typing: false,
+            },
+        }
+    }
+}
+
+/// Whether the card name contains the query ignoring case. Every name
+/// matches an empty query.
+pub fn name_matches(name: &str, query: &str) -> bool {
+    name.to_lowercase().contains(&query.to_lowercase())
+}
+
+/// The cards in the pile whose names match the query in pile order
+pub fn filter_pile<'a>(
+    cards: &CardCollection,
+    pile: &'a [CardInstance],
+    query: &str,
+) -> Vec<&'a CardInstance> {
+    pile.iter()
+        .filter(|instance| {
+            let card_id = &instance.card_id;
+            let name = cards.get(card_id).map_or(card_id.as_str(), |c| c.name);
+            name_matches(name, query)
+        })
+        .collect()
+}
+
+/// What the player is doing with their rest between combats
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RestChoice {
+    /// Picking how to spend the rest
+    Menu,
+    /// Picking a card in the master deck to upgrade
+    Upgrade,
+    /// Picking a card in the master deck to remove
+    Remove,
+}
+
+/// Resting after a victory before the next combat
+pub struct Rest {
+    pub shared_state: SharedState,
+    pub choice: RestChoice,
+    /// Index of the highlighted card in the master deck
+    pub selected: usize,
+}
+
+impl GuiStateMachine<Rest> {
+    pub fn new(choice: RestChoice) -> Self {
+        GuiStateMachine {
+            state: Rest {
+                shared_state: SharedState {},
+                choice,
+                selected: 0,
+            },
+        }
+    }
+}
+
+/// Shown once the combat is won or lost
+pub struct GameOver {
+    pub shared_state: SharedState,
+    pub outcome: Outcome,
+}
+
+impl GuiStateMachine<GameOver> {
+    pub fn new(outcome: Outcome) -> Self {
+        GuiStateMachine {
+            state: GameOver {
+                shared_state: SharedState {},
+                outcome,
+            },
+        }
+    }
+}
+
 #[cfg(test)]
 mod test_gui_state_machine {
     use super::*;
@@ -141,7 +407,6 @@ mod test_gui_state_machine {
         let combat_state = GuiStateMachine {
             state: Combat {
                 shared_state: SharedState {},
-                enemy_id,
             },
         };
 
@@ -166,4 +431,127 @@ mod test_gui_state_machine {
 
         assert_eq!(target_select_complete_state.state.target, enemy_id);
     }
+
+    #[test]
+    fn test_target_cursor_wraps() {
+        let mut state = TargetSelect {
+            shared_state: SharedState {},
+            targets: vec![10, 20, 30],
+            card_idx: 0,
+            selected_target_idx: 0,
+        };
+        assert_eq!(state.selected_target(), Some(10));
+
+        // Moving back from the first target wraps to the last
+        state.move_cursor(-1);
+        assert_eq!(state.selected_target(), Some(30));
+
+        // Moving forward from the last wraps to the first
+        state.move_cursor(1);
+        assert_eq!(state.selected_target(), Some(10));
+        state.move_cursor(4);
+        assert_eq!(state.selected_target(), Some(20));
+
+        // Nothing to select without targets
+        state.targets.clear();
+        state.selected_target_idx = 0;
+        state.move_cursor(1);
+        assert_eq!(state.selected_target(), None);
+    }
+
+    #[test]
+    fn test_filter_pile() {
+        use crate::engine::{CardId, CardTag, Target};
+
+        assert!(name_matches("Phasers", "phas"));
+        assert!(name_matches("Rapid Phasers", "PHASERS"));
+        assert!(name_matches("Shields", ""));
+        assert!(!name_matches("Shields", "phasers"));
+
+        let mut cards = CardCollection::new();
+        for (id, name) in [(CardId::SHIELDS, "Shields"), (CardId::PHASERS, "Phasers")].iter() {
+            cards.insert(Card {
+                id: *id,
+                name,
+                effects: vec![],
+                target: Target::Player,
+                tags: vec![CardTag::Skill],
+                cost: 1,
+            });
+        }
+        let mut game = GameState::new(cards, vec![]);
+        let pile = game.instances(&[
+            CardId::PHASERS,
+            CardId::SHIELDS,
+            CardId::new("unknown"),
+            CardId::PHASERS,
+        ]);
+
+        let ids = |query| -> Vec<CardId> {
+            filter_pile(&game.cards, &pile, query)
+                .iter()
+                .map(|card| card.card_id)
+                .collect()
+        };
+        assert_eq!(ids("ph"), vec![CardId::PHASERS, CardId::PHASERS]);
+        assert_eq!(ids("ields"), vec![CardId::SHIELDS]);
+        // Cards missing from the collection match on their ID
+        assert_eq!(ids("unk"), vec![CardId::new("unknown")]);
+        assert_eq!(ids("").len(), 4);
+        assert!(ids("torpedo").is_empty());
+    }
+
+    #[test]
+    fn test_scroll_clamping() {
+        // Can't scroll above the start
+        assert_eq!(scroll(0, -1, 10), 0);
+        assert_eq!(scroll(2, -5, 10), 0);
+
+        // Can't scroll past the last item
+        assert_eq!(scroll(9, 1, 10), 9);
+        assert_eq!(scroll(5, 10, 10), 9);
+
+        // Empty lists stay at the start
+        assert_eq!(scroll(0, 1, 0), 0);
+
+        assert_eq!(scroll(3, 2, 10), 5);
+    }
+
+    #[test]
+    fn test_hand_order() {
+        use crate::engine::{CardCollection, CardId, CardTag, Target};
+
+        let mut cards = CardCollection::new();
+        for (id, name, cost, tag) in [
+            ("torpedo", "Torpedo", 2, CardTag::Attack),
+            ("barrier", "Barrier", 3, CardTag::Skill),
+            ("laser", "Laser", 1, CardTag::Attack),
+        ]
+        .iter()
+        {
+            cards.insert(Card {
+                id: CardId::new(id),
+                name,
+                effects: vec![],
+                target: Target::Player,
+                tags: vec![*tag],
+                cost: *cost,
+            });
+        }
+        let torpedo = cards.get(&CardId::new("torpedo")).unwrap();
+        let barrier = cards.get(&CardId::new("barrier")).unwrap();
+        assert_eq!(HandOrder::Cost.compare(torpedo, barrier), Ordering::Less);
+        assert_eq!(HandOrder::Name.compare(torpedo, barrier), Ordering::Greater);
+        assert_eq!(HandOrder::Type.compare(torpedo, barrier), Ordering::Less);
+        assert_eq!(HandOrder::Draw.compare(torpedo, barrier), Ordering::Equal);
+
+        let mut game = GameState::new(cards, vec![]);
+        let hand = [CardId::new("torpedo"), CardId::new("barrier"), CardId::new("laser")];
+        game.hand = game.instances(&hand);
+        assert_eq!(HandOrder::Draw.hand_indices(&game), vec![0, 1, 2]);
+        assert_eq!(HandOrder::Cost.hand_indices(&game), vec![2, 0, 1]);
+        assert_eq!(HandOrder::Name.hand_indices(&game), vec![1, 2, 0]);
+        // Attacks stay in the order they were drawn
+        assert_eq!(HandOrder::Type.hand_indices(&game), vec![0, 2, 1]);
+    }
 }
@@ -0,0 +1,101 @@
+//! Keys the player presses to control the game. The defaults can be
+//! remapped by changing the fields of `KeyBindings`.
+
+/// A key press from whichever terminal backend is reading input. Only
+/// the keys the game responds to are kept, anything else is `Other`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Backspace,
+    Left,
+    Right,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Esc,
+    Char(char),
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyBindings {
+    /// End the player's turn
+    pub end_turn: Key,
+    /// Back out of a modal e.g. selecting a target
+    pub cancel: Key,
+    /// Show the key bindings
+    pub help: Key,
+    /// Play the next single target card against the last enemy
+    /// damaged
+    pub quick_target: Key,
+    /// Give up the run and see the summary
+    pub concede: Key,
+    /// Take back the last card played this turn
+    pub undo: Key,
+    /// Exit the game immediately
+    pub quit: Key,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            end_turn: Key::Char('e'),
+            cancel: Key::Esc,
+            help: Key::Char('?'),
+            quick_target: Key::Char('r'),
+            concede: Key::Char('c'),
+            undo: Key::Char('u'),
+            quit: Key::Char('q'),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Each binding with the name of what it does
+    pub fn bindings(&self) -> [(&'static str, Key); 7] {
+        [
+            ("End turn", self.end_turn),
+            ("Cancel", self.cancel),
+            ("Help", self.help),
+            ("Quick target", self.quick_target),
+            ("Concede", self.concede),
+            ("Undo", self.undo),
+            ("Quit", self.quit),
+        ]
+    }
+
+    /// A one line summary of the bindings to show the player
+    pub fn describe(&self) -> String {
+        self.bindings()
+            .iter()
+            .map(|(action, key)| format!("{}: {}", action, key_name(*key)))
+            .collect::<Vec<String>>()
+            .join("  /  ")
+    }
+}
+
+/// How the key is shown in prompts e.g. "Enter"
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Char('\t') => "Tab".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::Esc => "Esc".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+mod test_keys {
+    use super::*;
+
+    #[test]
+    fn test_default_bindings_are_unique() {
+        let bindings = KeyBindings::default().bindings();
+        for (idx, (action, key)) in bindings.iter().enumerate() {
+            for (other, other_key) in bindings[idx + 1..].iter() {
+                assert_ne!(key, other_key, "{} and {} share a key", action, other);
+            }
+        }
+    }
+}
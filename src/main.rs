@@ -1,161 +1,609 @@
 use backtrace::Backtrace;
-use std::panic::{self, PanicInfo};
-use std::{error::Error, io, time::Duration};
+use std::panic::{self, PanicHookInfo};
+use std::{error::Error, io, path::Path, time::Duration};
 
-use termion::{event::Key, input::MouseTerminal, raw::IntoRawMode, screen::AlternateScreen};
 use tui::{
-    backend::TermionBackend,
-    layout::{Alignment, Constraint, Direction, Layout},
-    style::{Color, Modifier, Style},
-    text::{Span, Spans},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap, Clear},
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
     Terminal,
 };
 
+#[cfg(feature = "termion-backend")]
+use std::io::Write;
+#[cfg(feature = "termion-backend")]
+use termion::{
+    input::MouseTerminal,
+    raw::{IntoRawMode, RawTerminal},
+    screen::AlternateScreen,
+};
+#[cfg(feature = "termion-backend")]
+use tui::backend::TermionBackend;
+
+mod args;
 mod engine;
 mod event;
 mod gui;
+mod keys;
+mod render;
+mod theme;
 
+use args::{parse_args, Options};
 use engine::*;
-use event::{Config, Event, Events};
+use event::{Clock, Config, Event, Events, InputBuffer};
 use gui::*;
+use keys::{Key, KeyBindings};
+use render::*;
+use theme::Theme;
+
+/// Optional theme file loaded at startup
+const THEME_FILE: &str = "theme.toml";
 
-const SPACE_SHIP: &str = "
-                           |-----------|
-           i               |===========|
-           |               |,---------.|                      __--~\\__--.
-    #---,'----`-_   `n     |`---------'|    `n    `n     ,--~~  __-/~~--'_____.
-       |~~~~~~~~~|---~---/=|___________|=\\---~-----~-----| .--~~  |  .__|     |
-     -[|.--_. ===|#####|-| |@@@@|+-+@@@| |]=###|/-++++-[| ||||___+_.  | `===='-.
-     -[|'==~'    |#####|-| |@@@@|+-+@@@| |]=###|\\-++++-[| ||||~~~+~'  | ,====.-'
-       |_________|---u---\\=|~~~~~~~~~~~|=/---u-----u-----| '--__  |  '~~|     |
-        \\       /=-   `    |,---------.|      `     `    `--__  ~~-\\__--.~~~~~'
-----=:===\\     /           |`---------'|                      ~~--_/~~--'
-      --<:\\___/--          |===========|
-                           |-----------|
-                           |___________|";
+/// Where the combat log is exported for bug reports
+const LOG_FILE: &str = "tunnelcast-log.toml";
+
+/// Something that can put the terminal back the way it was before the
+/// game started
+trait Restore {
+    fn restore(&mut self) -> io::Result<()>;
+}
+
+#[cfg(feature = "termion-backend")]
+impl<W: Write> Restore for RawTerminal<W> {
+    fn restore(&mut self) -> io::Result<()> {
+        write!(self, "{}{}", termion::cursor::Show, termion::screen::ToMainScreen)?;
+        self.flush()?;
+        self.suspend_raw_mode()
+    }
+}
+
+/// Restores the terminal when dropped so it's usable again whether
+/// the game exits normally or panics
+struct TerminalGuard<R: Restore>(R);
+
+impl<R: Restore> Drop for TerminalGuard<R> {
+    fn drop(&mut self) {
+        if let Err(err) = self.0.restore() {
+            eprintln!("Failed to restore the terminal: {}", err);
+        }
+    }
+}
 
 enum GuiState {
     Combat(GuiStateMachine<Combat>),
     TargetSelect(GuiStateMachine<TargetSelect>),
     TargetSelectComplete(GuiStateMachine<TargetSelectComplete>),
+    Scry(GuiStateMachine<Scry>),
+    Salvage(GuiStateMachine<Salvage>),
+    ConfirmEndTurn(GuiStateMachine<ConfirmEndTurn>),
+    ViewPile(GuiStateMachine<ViewPile>),
+    Rest(GuiStateMachine<Rest>),
+    GameOver(GuiStateMachine<GameOver>),
 }
 
 struct Game {
     game_state: GameState,
     gui_state: GuiState,
+    /// The panel scrolled by the scroll keys
+    scroll_panel: Panel,
+    log_offset: usize,
+    discard_offset: usize,
+    /// A message to show the player e.g. where the log was exported
+    notice: Option<String>,
+    /// Show how many of each card are in the deck instead of the
+    /// draw pile
+    show_deck_stats: bool,
+    /// Show what each attribute means instead of the log
+    show_legend: bool,
+    /// Show what the enemies will do this turn and next
+    show_forecast: bool,
+    hand_order: HandOrder,
+    keys: KeyBindings,
+    /// Play the next single target card against the last enemy
+    /// damaged instead of asking for a target
+    quick_target: bool,
+    run: Run,
+    /// Time spent in combat shown for speedruns
+    clock: Clock,
 }
 
 impl Game {
-    fn init_state() -> GameState {
+    /// The cards used unless a card file is given
+    fn cards() -> CardCollection {
         let mut cards = CardCollection::new();
 
         cards.insert(Card {
-            id: CardId::Shields,
+            id: CardId::SHIELDS,
             name: "Shields",
-            effects: vec![Box::new(IncreaseShields {})],
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
             target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
         });
 
         cards.insert(Card {
-            id: CardId::Phasers,
+            id: CardId::PHASERS,
             name: "Phasers",
-            effects: vec![Box::new(DamageHull {})],
+            effects: vec![Box::new(DamageHull { amount: 1 })],
             target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
         });
 
-        let mut init_deck = vec![
-            CardId::Shields,
-            CardId::Shields,
-            CardId::Shields,
-            CardId::Phasers,
-            CardId::Phasers,
-            CardId::Phasers,
-        ];
-        shuffle_deck(&mut init_deck);
+        cards.insert(Card {
+            id: CardId::AUTO_SHIELDS,
+            name: "Auto-Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Power],
+            cost: 2,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("power_surge"),
+            name: "Power Surge",
+            effects: vec![Box::new(IncreaseStrength { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("deploy_drone"),
+            name: "Deploy Drone",
+            effects: vec![Box::new(Summon {
+                template: EnemyTemplate {
+                    name: "Drone",
+                    hull: 3,
+                    shields: 0,
+                    ai: EnemyAi::Cycle(vec![]),
+                    art: Some(DRONE_ART),
+                },
+            })],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 2,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("overclock"),
+            name: "Overclock",
+            effects: vec![Box::new(DiscountNextCard { amount: 3 })],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 1,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("salvage"),
+            name: "Salvage",
+            effects: vec![Box::new(SalvageDiscard)],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 1,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("aimed_shot"),
+            name: "Aimed Shot",
+            effects: vec![Box::new(DamagePiercing { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 2,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("momentum"),
+            name: "Momentum",
+            effects: vec![Box::new(DamagePerCardPlayed { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
 
-        let mut game_state = GameState::new(cards, init_deck);
+        cards.insert(Card {
+            id: CardId::new("overload_reactor"),
+            name: "Overload Reactor",
+            effects: vec![Box::new(Sacrifice { hull_cost: 3, amount: 8, allow_lethal: false })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("scatter_shot"),
+            name: "Scatter Shot",
+            effects: vec![Box::new(DamageRange { min: 0, max: 3 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 1,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("particle_cannon"),
+            name: "Particle Cannon",
+            effects: vec![Box::new(ChargedDamage { amount: 1 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack, CardTag::Charge],
+            cost: 2,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("rapid_phasers"),
+            name: "Rapid Phasers",
+            effects: vec![Box::new(MultiHit { hits: 3, amount: 2 })],
+            target: Target::Single,
+            tags: vec![CardTag::Attack],
+            cost: 2,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("echo_chamber"),
+            name: "Echo Chamber",
+            effects: vec![Box::new(EchoNextCard)],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 1,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("brace"),
+            name: "Brace",
+            effects: vec![Box::new(IncreaseBlock { amount: 4 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+
+        cards.insert(Card {
+            id: CardId::new("recalibrate"),
+            name: "Recalibrate",
+            effects: vec![Box::new(DrawUntil { target_size: 5 })],
+            target: Target::Player,
+            tags: vec![CardTag::Tech],
+            cost: 1,
+        });
+
+        cards
+    }
+
+    fn init_state(
+        enemies: &[EnemyTemplate],
+        cards: CardCollection,
+        seed: Option<u64>,
+    ) -> Result<GameState, Box<dyn Error>> {
+        let init_deck = vec![
+            CardId::SHIELDS,
+            CardId::SHIELDS,
+            CardId::SHIELDS,
+            CardId::PHASERS,
+            CardId::PHASERS,
+            CardId::PHASERS,
+            CardId::AUTO_SHIELDS,
+            CardId::new("power_surge"),
+            CardId::new("deploy_drone"),
+            CardId::new("overclock"),
+            CardId::new("salvage"),
+            CardId::new("aimed_shot"),
+            CardId::new("momentum"),
+            CardId::new("overload_reactor"),
+            CardId::new("scatter_shot"),
+            CardId::new("particle_cannon"),
+            CardId::new("rapid_phasers"),
+            CardId::new("echo_chamber"),
+            CardId::new("recalibrate"),
+            CardId::new("brace"),
+        ];
 
-        // Add player
         let mut s = State::new();
         s.insert(Attribute::Hull, 10);
+        s.insert(Attribute::MaxHull, 10);
         s.insert(Attribute::Shields, 10);
+        s.insert(Attribute::MaxShields, 10);
         let player = Player { name: String::from("Player"), state: s };
-        let player_id = 1;
-        game_state.add_entity(Some(player_id), Box::new(player));
-        game_state.player = player_id;
 
-        // Add an enemy
-        let mut s = State::new();
-        s.insert(Attribute::Hull, 10);
-        s.insert(Attribute::Shields, 10);
-        let enemy = Enemy { name: String::from("Battleship"), state: s };
-        let enemy_id = 2;
-        game_state.add_entity(Some(enemy_id), Box::new(enemy));
-        game_state.enemy = Some(enemy_id);
+        let mut builder = GameStateBuilder::new(cards, init_deck).player(player);
+        if let Some(seed) = seed {
+            builder = builder.seed(seed);
+        }
+        let mut game_state = builder
+            .enemies(enemies)
+            // Firing phasers twice in a row deals extra damage
+            .combo(Combo {
+                previous: CardId::PHASERS,
+                card: CardId::PHASERS,
+                bonus: vec![Box::new(DamageHull { amount: 1 })],
+            })
+            .build();
+        game_state.shuffle_draw();
+
+        // Fail now rather than when a missing card is played
+        if let Err(missing) = game_state.validate() {
+            return Err(format!("Deck has cards missing from the collection: {:?}", missing).into());
+        }
+
+        game_state.actions.push_back(Action::BeginTurn);
+        tick(&mut game_state);
+
+        Ok(game_state)
+    }
+
+    /// The flagship fought at the start and end of the run
+    fn battleship() -> EnemyTemplate {
+        EnemyTemplate {
+            name: "Battleship",
+            hull: 10,
+            shields: 10,
+            ai: EnemyAi::Cycle(vec![
+                EnemyIntent::Attack(1),
+                EnemyIntent::Attack(1),
+                EnemyIntent::Defend(2),
+            ]),
+            art: Some(BATTLESHIP_ART),
+        }
+    }
 
-        draw_hand(&mut game_state, 4);
+    /// A small ship that attacks in pairs
+    fn scout() -> EnemyTemplate {
+        EnemyTemplate {
+            name: "Scout",
+            hull: 6,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(2), EnemyIntent::Defend(1)]),
+            art: Some(DRONE_ART),
+        }
+    }
 
-        game_state
+    /// The fights in a run in the order they're fought
+    fn encounters() -> Vec<Encounter> {
+        vec![
+            Encounter {
+                enemies: vec![Self::battleship()],
+            },
+            Encounter {
+                enemies: vec![Self::scout(), Self::scout()],
+            },
+            Encounter {
+                enemies: vec![Self::scout(), Self::battleship()],
+            },
+        ]
     }
 
+    /// A game with the default options
+    #[cfg(test)]
     fn new() -> Self {
-        let game_state = Self::init_state();
-        let gui_state = GuiState::Combat(GuiStateMachine::<Combat>::new(game_state.enemy.unwrap()));
+        Self::with_options(&Options::default()).expect("Failed to start the game")
+    }
 
-        Self {
+    /// Start a run with the cards and seed from the command line
+    fn with_options(options: &Options) -> Result<Self, Box<dyn Error>> {
+        let cards = match &options.cards {
+            Some(path) => CardCollection::from_file(path)?,
+            None => Self::cards(),
+        };
+        let run = Run::new(Self::encounters());
+        let enemies = run.current().map_or(&[][..], |encounter| &encounter.enemies);
+        let game_state = Self::init_state(enemies, cards, options.seed)?;
+        let gui_state = GuiState::Combat(GuiStateMachine::<Combat>::new());
+        let mut clock = Clock::default();
+        clock.start();
+
+        Ok(Self {
             game_state,
             gui_state,
+            scroll_panel: Panel::Log,
+            log_offset: 0,
+            discard_offset: 0,
+            notice: None,
+            show_deck_stats: false,
+            show_legend: false,
+            show_forecast: false,
+            hand_order: HandOrder::Draw,
+            keys: KeyBindings::default(),
+            quick_target: false,
+            run,
+            clock,
+        })
+    }
+
+    fn export_log(&mut self) {
+        let notice = match self.game_state.export_log(Path::new(LOG_FILE)) {
+            Ok(()) => format!("Log exported to {}", LOG_FILE),
+            Err(err) => format!("Failed to export log: {}", err),
+        };
+        self.notice = Some(notice);
+    }
+
+    /// Scroll the selected panel by `delta` items
+    fn scroll_selected_panel(&mut self, delta: i32) {
+        match self.scroll_panel {
+            Panel::Log => {
+                self.log_offset = scroll(self.log_offset, delta, self.game_state.events.len());
+            }
+            Panel::Discard => {
+                self.discard_offset =
+                    scroll(self.discard_offset, delta, self.game_state.discard.len());
+            }
         }
     }
 
+    /// Leave the rest and start the next combat
+    fn next_combat(&mut self) {
+        // Apply the rest first so the new draw pile includes any
+        // changes to the deck
+        tick(&mut self.game_state);
+        self.run.start_encounter(&mut self.game_state);
+        self.clock.start();
+        let next_gui_state = GuiStateMachine::<Combat>::new();
+        self.gui_state = GuiState::Combat(next_gui_state);
+    }
+
+    /// The index in the hand of the card shown at the number key's
+    /// position
+    fn hand_index(&self, num_char: char) -> Option<usize> {
+        let position = num_char.to_digit(10).filter(|n| *n > 0)? as usize - 1;
+        self.hand_order.hand_indices(&self.game_state).get(position).copied()
+    }
+
+    /// Whether the actions from the last input have finished resolving
+    fn ready_for_input(&self) -> bool {
+        self.game_state.awaiting_input() && self.game_state.actions.is_empty()
+    }
+
+    /// Whether keys are being typed into a text field e.g. the pile
+    /// filter so they shouldn't trigger any binding including quit
+    fn is_typing(&self) -> bool {
+        matches!(&self.gui_state, GuiState::ViewPile(state) if state.state.typing)
+    }
+
+    /// Handle an event from the game loop. Returns false once the
+    /// player quits.
+    fn handle_event(
+        &mut self,
+        event: Event<Key>,
+        inputs: &mut InputBuffer<Key>,
+        tick_rate: Duration,
+    ) -> bool {
+        match event {
+            Event::Tick => {
+                self.clock.tick(tick_rate);
+                self.update();
+            }
+            // Typed keys go to the text field before checking for quit
+            Event::Input(input) if self.is_typing() => inputs.push(input),
+            Event::Input(input) if input == self.keys.quit => return false,
+            Event::Input(input) => inputs.push(input),
+        };
+
+        // Handle each key once the actions from the last one have
+        // resolved so keys pressed in quick succession aren't lost
+        while let Some(input) = inputs.pop_if(self.ready_for_input()) {
+            self.handle_keyboard_input(input);
+        }
+
+        true
+    }
+
     fn handle_keyboard_input(&mut self, input: Key) -> &mut Self {
+        // Ignore input until the last action finishes animating
+        if !self.game_state.awaiting_input() {
+            return self;
+        }
+
         match self.gui_state {
             GuiState::Combat(ref state) => {
                 match input {
-                    Key::Char('e') => {
-                        self.game_state.action = Action::EndTurn;
+                    key if key == self.keys.end_turn => {
+                        if self.game_state.should_confirm_end_turn() {
+                            let next_gui_state =
+                                GuiStateMachine::<ConfirmEndTurn>::transition_from(state, ());
+                            self.gui_state = GuiState::ConfirmEndTurn(next_gui_state);
+                        } else {
+                            self.game_state.actions.push_back(Action::EndTurn);
+                        }
                     }
-                    Key::Char(num_char) => {
-                        if ['1', '2', '3', '4', '5', '6', '7', '8', '9'].contains(&num_char)
-                            && num_char.to_digit(10).unwrap()
-                            <= self.game_state.hand.len() as u32
-                        {
-                            let card_idx = num_char.to_digit(10).unwrap() as usize;
-                            let card_idx = (card_idx - 1) as u32; // Convert to vector index
-                            let card_id = self.game_state.hand[card_idx as usize];
-                            let selected_card = self.game_state.cards.get(&card_id).unwrap();
-
-                            let next_gui_state = GuiStateMachine::<PlayCard>::transition_from(
-                                state,
-                                PlayCardArgs { card_idx },
-                            );
-
-                            // Determine the target of the card or
-                            // prompt the user
-                            match selected_card.target {
-                                Target::Player => {
-                                    self.game_state.action = Action::PlayCard(
-                                        self.game_state.player,
-                                        card_idx as i32,
-                                    );
-                                }
-                                Target::Single => {
-                                    // TODO If there is only a single
-                                    // enemy then skip the transition
-                                    let enemy = self
-                                        .game_state
-                                        .enemy
-                                        .expect("Can't target if there are no enemies");
-                                    let next_gui_state =
-                                        GuiStateMachine::<TargetSelect>::transition_from(
-                                            &next_gui_state,
-                                            TargetSelectArgs {
-                                                card_idx,
-                                                targets: vec![enemy],
-                                            },
-                                        );
-                                    self.gui_state = GuiState::TargetSelect(next_gui_state);
+                    key if key == self.keys.help => {
+                        self.notice = Some(self.keys.describe());
+                    }
+                    key if key == self.keys.concede => {
+                        self.game_state.actions.push_back(Action::Concede);
+                    }
+                    key if key == self.keys.undo => {
+                        if self.game_state.undo.is_some() {
+                            self.game_state.actions.push_back(Action::UndoCard);
+                        } else {
+                            self.notice = Some(String::from("No card to undo this turn"));
+                        }
+                    }
+                    key if key == self.keys.quick_target => {
+                        self.quick_target = !self.quick_target;
+                        let notice = match (self.quick_target, self.game_state.last_targeted) {
+                            (false, _) => "Quick target cancelled",
+                            (true, Some(_)) => "Next card targets the last enemy hit",
+                            (true, None) => "No enemy hit yet to target",
+                        };
+                        self.notice = Some(notice.to_string());
+                    }
+                    Key::Char('x') => self.export_log(),
+                    // Play out the turn automatically while debugging
+                    Key::Char('a') if cfg!(debug_assertions) => {
+                        autoplay_turn(&mut self.game_state);
+                        if self.game_state.outcome().is_none() {
+                            self.game_state.actions.push_back(Action::BeginTurn);
+                        }
+                    }
+                    Key::Char('d') => {
+                        self.show_deck_stats = !self.show_deck_stats;
+                    }
+                    Key::Char('l') => {
+                        self.show_legend = !self.show_legend;
+                    }
+                    Key::Char('f') => {
+                        self.show_forecast = !self.show_forecast;
+                    }
+                    Key::Char('s') => {
+                        self.hand_order = self.hand_order.next();
+                    }
+                    Key::Char('v') => {
+                        let next_gui_state = GuiStateMachine::<ViewPile>::new(PileKind::Draw);
+                        self.gui_state = GuiState::ViewPile(next_gui_state);
+                    }
+                    Key::Char('\t') => {
+                        self.scroll_panel = self.scroll_panel.next();
+                    }
+                    Key::Char('j') | Key::PageDown => self.scroll_selected_panel(1),
+                    Key::Char('k') | Key::PageUp => self.scroll_selected_panel(-1),
+                    Key::Char(num_char)
+                        if self
+                            .hand_index(num_char)
+                            .is_some_and(|idx| self.game_state.can_play(idx)) =>
+                    {
+                        let card_idx = self.hand_index(num_char).unwrap() as u32;
+                        let card_id = self.game_state.hand[card_idx as usize].card_id;
+                        let selected_card = self.game_state.cards.get(&card_id).unwrap();
+
+                        let next_gui_state = GuiStateMachine::<PlayCard>::transition_from(
+                            state,
+                            PlayCardArgs { card_idx },
+                        );
+
+                        // Determine the target of the card or
+                        // prompt the user
+                        match selected_card.target {
+                            Target::Player | Target::All => {
+                                self.game_state.actions.push_back(Action::PlayCard(
+                                    self.game_state.player,
+                                    card_idx as i32,
+                                ));
+                            }
+                            Target::Single | Target::Ally => {
+                                let targets =
+                                    selected_card.target.valid_targets(&self.game_state);
+                                let quick_target = if self.quick_target {
+                                    self.game_state.quick_target(card_idx as usize)
+                                } else {
+                                    None
+                                };
+                                self.quick_target = false;
+                                match (targets.len(), quick_target) {
+                                    (0, _) => (),
+                                    // Skip prompting when there is only
+                                    // one possible target
+                                    (1, _) => {
+                                        let action = Action::PlayCard(targets[0], card_idx as i32);
+                                        self.game_state.actions.push_back(action);
+                                    }
+                                    (_, Some(target)) => {
+                                        let action = Action::PlayCard(target, card_idx as i32);
+                                        self.game_state.actions.push_back(action);
+                                    }
+                                    _ => {
+                                        let next_gui_state =
+                                            GuiStateMachine::<TargetSelect>::transition_from(
+                                                &next_gui_state,
+                                                TargetSelectArgs { card_idx, targets },
+                                            );
+                                        self.gui_state = GuiState::TargetSelect(next_gui_state);
+                                    }
                                 }
                             }
                         }
@@ -165,19 +613,22 @@ impl Game {
             }
             GuiState::TargetSelect(ref mut state) => {
                 match input {
-                    Key::Char('q') => {
+                    key if key == self.keys.cancel => {
                         // Cancel by resetting back to initial GUI
                         // state
-                        let next_gui_state =
-                            GuiStateMachine::<Combat>::new(self.game_state.enemy.unwrap());
+                        let next_gui_state = GuiStateMachine::<Combat>::new();
                         self.gui_state = GuiState::Combat(next_gui_state);
                     }
-                    Key::Char('1') => {
+                    Key::Char(num_char)
+                        if ['1', '2', '3', '4', '5', '6', '7', '8', '9'].contains(&num_char)
+                            && num_char.to_digit(10).unwrap() as usize
+                                <= state.state.targets.len() =>
+                    {
                         // Transition back to Combat state and
                         // play the card now that the player
                         // selected a target
-                        let entity_idx = 1;
-                        let target = self.game_state.entities[entity_idx];
+                        let target_idx = num_char.to_digit(10).unwrap() as usize - 1;
+                        let target = state.state.targets[target_idx];
                         let next_gui_state =
                             GuiStateMachine::<TargetSelectComplete>::transition_from(
                                 state,
@@ -185,6 +636,18 @@ impl Game {
                             );
                         self.gui_state = GuiState::TargetSelectComplete(next_gui_state);
                     }
+                    Key::Up | Key::Left => state.state.move_cursor(-1),
+                    Key::Down | Key::Right => state.state.move_cursor(1),
+                    Key::Char('\n') => {
+                        if let Some(target) = state.state.selected_target() {
+                            let next_gui_state =
+                                GuiStateMachine::<TargetSelectComplete>::transition_from(
+                                    state,
+                                    TargetSelectCompleteArgs { target },
+                                );
+                            self.gui_state = GuiState::TargetSelectComplete(next_gui_state);
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -197,89 +660,292 @@ impl Game {
                 let target_id = state.state.target;
                 let card_idx = state.state.card_idx;
 
-                let next_gui_state = GuiStateMachine::<Combat>::new(self.game_state.enemy.unwrap());
+                let next_gui_state = GuiStateMachine::<Combat>::new();
                 self.gui_state = GuiState::Combat(next_gui_state);
 
                 // Set the action to be processed next tick
-                self.game_state.action = Action::PlayCard(target_id, card_idx as i32);
+                self.game_state.actions.push_back(Action::PlayCard(target_id, card_idx as i32));
             }
+            GuiState::Scry(_) => match input {
+                Key::Char(num_char)
+                    if ['1', '2', '3', '4', '5', '6', '7', '8', '9'].contains(&num_char)
+                        && num_char.to_digit(10).unwrap() as usize
+                            <= self.game_state.scry.len() =>
+                {
+                    let position = num_char.to_digit(10).unwrap() as usize - 1;
+                    self.game_state.actions.push_back(Action::ScryDiscard(position));
+                }
+                Key::Char('\n') => {
+                    self.game_state.actions.push_back(Action::EndScry);
+                }
+                _ => (),
+            },
+            GuiState::Salvage(_) => match input {
+                Key::Char(num_char)
+                    if ['1', '2', '3', '4', '5', '6', '7', '8', '9'].contains(&num_char)
+                        && num_char.to_digit(10).unwrap() as usize
+                            <= self.game_state.discard.len() =>
+                {
+                    let position = num_char.to_digit(10).unwrap() as usize - 1;
+                    self.game_state.actions.push_back(Action::SalvageCard(position));
+                }
+                _ => (),
+            },
+            GuiState::ConfirmEndTurn(_) => match input {
+                Key::Char('y') => {
+                    self.game_state.actions.push_back(Action::EndTurn);
+                    let next_gui_state = GuiStateMachine::<Combat>::new();
+                    self.gui_state = GuiState::Combat(next_gui_state);
+                }
+                key if key == Key::Char('n') || key == self.keys.cancel => {
+                    let next_gui_state = GuiStateMachine::<Combat>::new();
+                    self.gui_state = GuiState::Combat(next_gui_state);
+                }
+                _ => (),
+            },
+            GuiState::ViewPile(ref mut state) if state.state.typing => match input {
+                Key::Char('\n') => state.state.typing = false,
+                Key::Esc => {
+                    state.state.query.clear();
+                    state.state.typing = false;
+                }
+                Key::Backspace => {
+                    state.state.query.pop();
+                }
+                Key::Char(c) => state.state.query.push(c),
+                _ => (),
+            },
+            GuiState::ViewPile(ref mut state) => match input {
+                Key::Char('/') => state.state.typing = true,
+                Key::Char('\t') => {
+                    // Keep filtering the next pile by the same query
+                    let mut next_gui_state =
+                        GuiStateMachine::<ViewPile>::new(state.state.pile.next());
+                    next_gui_state.state.query = state.state.query.clone();
+                    self.gui_state = GuiState::ViewPile(next_gui_state);
+                }
+                key if key == Key::Char('v') || key == Key::Esc || key == self.keys.cancel => {
+                    let next_gui_state = GuiStateMachine::<Combat>::new();
+                    self.gui_state = GuiState::Combat(next_gui_state);
+                }
+                _ => (),
+            },
+            GuiState::Rest(ref mut state) => {
+                let deck_size = self.game_state.master_deck.len();
+                match (state.state.choice, input) {
+                    (RestChoice::Menu, Key::Char('1')) => {
+                        self.game_state.actions.push_back(Action::RestHeal);
+                        self.next_combat();
+                    }
+                    (RestChoice::Menu, Key::Char('2')) if deck_size > 0 => {
+                        let next_gui_state = GuiStateMachine::<Rest>::new(RestChoice::Upgrade);
+                        self.gui_state = GuiState::Rest(next_gui_state);
+                    }
+                    (RestChoice::Menu, Key::Char('3')) if deck_size > 0 => {
+                        let next_gui_state = GuiStateMachine::<Rest>::new(RestChoice::Remove);
+                        self.gui_state = GuiState::Rest(next_gui_state);
+                    }
+                    (RestChoice::Menu, _) => (),
+                    (_, Key::Up) | (_, Key::Char('k')) => {
+                        state.state.selected = (state.state.selected + deck_size - 1) % deck_size;
+                    }
+                    (_, Key::Down) | (_, Key::Char('j')) => {
+                        state.state.selected = (state.state.selected + 1) % deck_size;
+                    }
+                    (choice, Key::Char('\n')) => {
+                        let action = match choice {
+                            RestChoice::Upgrade => Action::UpgradeCard(state.state.selected),
+                            _ => Action::RemoveCard(state.state.selected),
+                        };
+                        self.game_state.actions.push_back(action);
+                        self.next_combat();
+                    }
+                    (_, key) if key == self.keys.cancel => {
+                        let next_gui_state = GuiStateMachine::<Rest>::new(RestChoice::Menu);
+                        self.gui_state = GuiState::Rest(next_gui_state);
+                    }
+                    _ => (),
+                }
+            }
+            GuiState::GameOver(_) => (),
         }
 
         self
     }
 
     fn update(&mut self) -> &mut Self {
+        if let GuiState::TargetSelectComplete(ref state) = self.gui_state {
+            // Reset to combat state
+            // TODO maybe make this an explicit transition?
+            let target_id = state.state.target;
+            let card_idx = state.state.card_idx;
+
+            let next_gui_state = GuiStateMachine::<Combat>::new();
+            self.gui_state = GuiState::Combat(next_gui_state);
+
+            // Set the action to be processed next tick
+            self.game_state.actions.push_back(Action::PlayCard(target_id, card_idx as i32));
+        }
+        // Move the game forward one tick
+        let applied = self.game_state.history.len();
+        tick(&mut self.game_state);
+
+        // Start the next turn once the enemies have acted
+        if self.game_state.history[applied..].contains(&Action::EndTurn) {
+            self.game_state.actions.push_back(Action::BeginTurn);
+        }
+
+        // Show or hide the revealed cards
         match self.gui_state {
-            GuiState::TargetSelectComplete(ref state) => {
-                // Reset to combat state
-                // TODO maybe make this an explicit transition?
-                let target_id = state.state.target;
-                let card_idx = state.state.card_idx;
+            GuiState::Combat(ref state) if !self.game_state.scry.is_empty() => {
+                let next_gui_state = GuiStateMachine::<Scry>::transition_from(state, ());
+                self.gui_state = GuiState::Scry(next_gui_state);
+            }
+            GuiState::Scry(_) if self.game_state.scry.is_empty() => {
+                let next_gui_state = GuiStateMachine::<Combat>::new();
+                self.gui_state = GuiState::Combat(next_gui_state);
+            }
+            _ => (),
+        }
 
-                let next_gui_state = GuiStateMachine::<Combat>::new(self.game_state.enemy.unwrap());
+        // Show or hide the discard pile to salvage from
+        match self.gui_state {
+            GuiState::Combat(ref state) if self.game_state.salvaging => {
+                let next_gui_state = GuiStateMachine::<Salvage>::transition_from(state, ());
+                self.gui_state = GuiState::Salvage(next_gui_state);
+            }
+            GuiState::Salvage(_) if !self.game_state.salvaging => {
+                let next_gui_state = GuiStateMachine::<Combat>::new();
                 self.gui_state = GuiState::Combat(next_gui_state);
+            }
+            _ => (),
+        }
 
-                // Set the action to be processed next tick
-                self.game_state.action = Action::PlayCard(target_id, card_idx as i32);
+        // Rest after winning the combat unless it was the last one in
+        // the run or end the game once it's lost
+        match (self.game_state.outcome(), &self.gui_state) {
+            (Some(Outcome::Victory), GuiState::Rest(_))
+            | (Some(Outcome::Victory), GuiState::GameOver(_)) => (),
+            (Some(Outcome::Victory), _) => {
+                self.clock.stop();
+                self.gui_state = match self.run.advance(&mut self.game_state) {
+                    Some(_) => GuiState::Rest(GuiStateMachine::<Rest>::new(RestChoice::Menu)),
+                    None => GuiState::GameOver(GuiStateMachine::<GameOver>::new(Outcome::Victory)),
+                };
+            }
+            (Some(outcome), _) => {
+                self.clock.stop();
+                self.gui_state = GuiState::GameOver(GuiStateMachine::<GameOver>::new(outcome));
             }
-            _ => ()
+            (None, _) => (),
         }
-        // Move the game forward one tick
-        tick(&mut self.game_state);
-        // Await user input
-        self.game_state.action = Action::Await;
 
         self
     }
 }
 
 /// Shows a backtrace if the program panics
-fn panic_hook(info: &PanicInfo<'_>) {
-    if cfg!(debug_assertions) {
-        let location = info.location().unwrap();
-
-        let msg = match info.payload().downcast_ref::<&'static str>() {
-            Some(s) => *s,
-            None => match info.payload().downcast_ref::<String>() {
-                Some(s) => &s[..],
-                None => "Box<Any>",
-            },
-        };
+fn panic_hook(info: &PanicHookInfo<'_>) {
+    let location = info.location().unwrap();
+
+    let msg = match info.payload().downcast_ref::<&'static str>() {
+        Some(s) => *s,
+        None => match info.payload().downcast_ref::<String>() {
+            Some(s) => &s[..],
+            None => "Box<Any>",
+        },
+    };
 
-        let stacktrace: String = format!("{:?}", Backtrace::new()).replace('\n', "\n\r");
+    // Only debug builds have useful backtraces
+    let stacktrace: String = if cfg!(debug_assertions) {
+        format!("{:?}", Backtrace::new()).replace('\n', "\n\r")
+    } else {
+        String::new()
+    };
 
-        println!(
-            "{}thread '<unnamed>' panicked at '{}', {}\n\r{}",
-            termion::screen::ToMainScreen,
-            msg,
-            location,
-            stacktrace
-        );
-    }
+    // The terminal guard restores the rest of the terminal as the
+    // panic unwinds
+    #[cfg(feature = "termion-backend")]
+    print!("{}{}", termion::screen::ToMainScreen, termion::cursor::Show);
+    println!(
+        "thread '<unnamed>' panicked at '{}', {}\n\r{}",
+        msg,
+        location,
+        stacktrace
+    );
 }
 
-fn run() -> Result<(), Box<dyn Error>> {
-    // Initialize the terminal
-    let stdout = io::stdout().into_raw_mode()?;
-    let stdout = MouseTerminal::from(stdout);
+#[cfg(not(feature = "termion-backend"))]
+compile_error!("A terminal backend feature must be enabled e.g. termion-backend");
+
+/// Set up the terminal for drawing the game. This is the only place
+/// that knows which backend is used so supporting another one e.g.
+/// crossterm means adding a version of this behind its own feature.
+/// The guard restores the terminal when dropped and must outlive the
+/// returned terminal.
+#[cfg(feature = "termion-backend")]
+fn init_terminal() -> io::Result<(
+    TerminalGuard<RawTerminal<io::Stdout>>,
+    Terminal<impl Backend>,
+)> {
+    let guard = TerminalGuard(io::stdout().into_raw_mode()?);
+    let stdout = MouseTerminal::from(io::stdout());
     let stdout = AlternateScreen::from(stdout);
     let backend = TermionBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let terminal = Terminal::new(backend)?;
+
+    Ok((guard, terminal))
+}
 
-    // Setup event handlers
-    let config = Config {
-        tick_rate: Duration::from_millis(250),
-        ..Default::default()
+fn run() -> Result<(), Box<dyn Error>> {
+    // Load the options, cards and theme before switching screens so
+    // errors are visible
+    let options = parse_args(std::env::args().skip(1))?;
+    let game = Game::with_options(&options)?;
+    let theme_path = Path::new(THEME_FILE);
+    let theme = if theme_path.exists() {
+        Theme::from_file(theme_path)?
+    } else {
+        Theme::default()
     };
+
+    // The guard is declared first so it's dropped last after the
+    // alternate screen is closed
+    let (_guard, mut terminal) = init_terminal()?;
+
+    game_loop(&mut terminal, &theme, game)
+}
+
+/// Draw the game and handle input until the player quits
+fn game_loop<B: Backend>(
+    terminal: &mut Terminal<B>,
+    theme: &Theme,
+    mut game: Game,
+) -> Result<(), Box<dyn Error>> {
+    // Setup event handlers. Quitting is handled by the game using
+    // its key bindings.
+    let config = Config::with_tick_rate(Duration::from_millis(250));
     let events = Events::with_config(config);
 
-    // Initialize the game
-    let mut game = Game::new();
+    let mut inputs = InputBuffer::new();
 
     loop {
         terminal.draw(|f| {
             let game_state = &game.game_state;
 
+            if let GuiState::GameOver(state) = &game.gui_state {
+                let outcome = state.state.outcome;
+                let game_over = build_game_over(outcome, &game_state.stats, game.keys.quit, theme);
+                f.render_widget(game_over, f.size());
+                return;
+            }
+
+            if let GuiState::Rest(state) = &game.gui_state {
+                let rest = build_rest(game_state, &state.state, game.keys.cancel, theme);
+                f.render_widget(rest, f.size());
+                return;
+            }
+
             let chunks = Layout::default()
                 .direction(Direction::Vertical)
                 .constraints(
@@ -293,53 +959,36 @@ fn run() -> Result<(), Box<dyn Error>> {
                 )
                 .split(f.size());
 
-            // Display the player's status
-
-            let player_state = game_state
-                .entity_state
-                .get(&game_state.player)
-                .expect("Failed to get player's state")
-                .get_state();
-
-            // Use deref coercion to convert to &str. Using just &
-            // operator, the compiler will automatically insert an
-            // appropriate amount of derefs (*) based on the context
-            let player_status: &str = &format!(
-                "Shields: {}  /  Hull: {}",
-                player_state.get(&Attribute::Shields).unwrap(),
-                player_state.get(&Attribute::Hull).unwrap(),
+            f.render_widget(build_status_paragraph(game_state, theme), chunks[0]);
+            // The clock sits in the corner inside the status border
+            let clock_area = Rect::new(
+                chunks[0].x + 1,
+                chunks[0].y + 1,
+                chunks[0].width.saturating_sub(2),
+                chunks[0].height.min(1),
             );
+            f.render_widget(build_clock(game.clock.elapsed, theme), clock_area);
 
-            let status_bar = Paragraph::new(player_status)
-                .block(Block::default().borders(Borders::ALL).title("Status"))
-                .alignment(Alignment::Center);
-
-            f.render_widget(status_bar, chunks[0]);
-
-            // Display the enemy
-
-            let enemy_state = game_state
-                .entity_state
-                .get(&game_state.enemy.unwrap())
-                .expect("Failed to get enemy's state")
-                .get_state();
-
-            let enemy_status: &str = &format!(
-                "Shields: {}  /  Hull: {}",
-                enemy_state.get(&Attribute::Shields).unwrap(),
-                enemy_state.get(&Attribute::Hull).unwrap(),
-            );
-
-            let mut text: Vec<Spans> = SPACE_SHIP.split('\n').map(|l| Spans::from(l)).collect();
-            text.push(Spans::from(""));
-            text.push(Spans::from(enemy_status));
-
-            let paragraph = Paragraph::new(text)
-                .block(Block::default().borders(Borders::ALL))
-                .style(Style::default().fg(Color::LightYellow))
-                .alignment(Alignment::Left);
+            let enemy_chunks = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Percentage(70), Constraint::Percentage(30)].as_ref())
+                .split(chunks[1]);
 
-            f.render_widget(paragraph, chunks[1]);
+            let turn_order_chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(3)].as_ref())
+                .split(enemy_chunks[0]);
+
+            f.render_widget(build_enemy_paragraph(game_state, theme), turn_order_chunks[0]);
+            f.render_widget(build_turn_order(game_state, theme), turn_order_chunks[1]);
+            if game.show_legend {
+                f.render_widget(build_legend(theme), enemy_chunks[1]);
+            } else {
+                f.render_widget(
+                    build_log_list(game_state, game.log_offset, game.scroll_panel, theme),
+                    enemy_chunks[1],
+                );
+            }
 
             // Show the deck piles (draw pile, hand, discard pile)
 
@@ -355,129 +1004,431 @@ fn run() -> Result<(), Box<dyn Error>> {
                 )
                 .split(chunks[2]);
 
-            let draw_pile = Block::default()
-                .title("List")
-                .borders(Borders::ALL)
-                .title("Draw");
+            if game.show_deck_stats {
+                f.render_widget(build_deck_stats(game_state, theme), horizontal_chunks[0]);
+            } else {
+                f.render_widget(build_draw_pile(theme), horizontal_chunks[0]);
+            }
+            f.render_widget(
+                build_hand_list(game_state, game.hand_order, theme),
+                horizontal_chunks[1],
+            );
+            f.render_widget(
+                build_discard_list(game_state, game.discard_offset, game.scroll_panel, theme),
+                horizontal_chunks[2],
+            );
 
-            f.render_widget(draw_pile, horizontal_chunks[0]);
+            let notice = game.notice.as_deref();
+            f.render_widget(
+                build_prompt(game_state, notice, game.hand_order, game.keys.end_turn, theme),
+                chunks[3],
+            );
 
-            let items: Vec<ListItem> = game_state
-                .hand
-                .iter()
-                .map(|i| ListItem::new(game_state.cards.get(i).unwrap().name))
-                .collect();
+            match &game.gui_state {
+                GuiState::TargetSelect(state) => {
+                    let lines = target_modal_lines(&state.state, game_state, theme);
+                    render_modal(f, "Target", lines, theme);
+                }
+                GuiState::Scry(_) => {
+                    render_modal(f, "Scry", scry_modal_lines(game_state, theme), theme);
+                }
+                GuiState::Salvage(_) => {
+                    render_modal(f, "Salvage", salvage_modal_lines(game_state, theme), theme);
+                }
+                GuiState::ViewPile(state) => {
+                    let lines = pile_modal_lines(game_state, &state.state, theme);
+                    render_modal(f, "Piles", lines, theme);
+                }
+                GuiState::ConfirmEndTurn(_) => {
+                    let lines = confirm_end_turn_lines(game_state, theme);
+                    render_modal(f, "End Turn", lines, theme);
+                }
+                GuiState::Combat(_) if game.show_forecast => {
+                    render_modal(f, "Forecast", forecast_lines(game_state, theme), theme);
+                }
+                _ => (),
+            }
+        })?;
+
+        if !game.handle_event(events.next()?, &mut inputs, config.tick_rate) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    panic::set_hook(Box::new(|info| {
+        panic_hook(info);
+    }));
 
-            let list = List::new(items)
-                .block(Block::default().borders(Borders::ALL).title("Hand"))
-                .style(Style::default().fg(Color::White))
-                .highlight_style(Style::default().add_modifier(Modifier::ITALIC))
-                .highlight_symbol(">>");
+    run()
+}
 
-            f.render_widget(list, horizontal_chunks[1]);
+#[cfg(test)]
+mod test_game_input {
+    use super::*;
+    use std::{cell::Cell, rc::Rc};
+
+    fn add_enemy(game: &mut Game) -> EntityId {
+        let frigate = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 10,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        spawn_enemies(&mut game.game_state, &[frigate])[0]
+    }
 
-            let discard_items = vec![];
+    #[test]
+    fn test_single_target_skips_modal() {
+        let mut game = Game::new();
+        game.game_state.hand = game.game_state.instances(&[CardId::PHASERS]);
+        let enemy_id = game.game_state.enemy.unwrap();
 
-            let discard_pile = List::new(discard_items)
-                .block(Block::default().borders(Borders::ALL).title("Discard"))
-                .style(Style::default().fg(Color::White))
-                .highlight_style(Style::default().add_modifier(Modifier::ITALIC))
-                .highlight_symbol(">>");
+        game.handle_keyboard_input(Key::Char('1'));
 
-            f.render_widget(discard_pile, horizontal_chunks[2]);
+        assert!(matches!(game.gui_state, GuiState::Combat(_)));
+        assert_eq!(
+            game.game_state.actions.front(),
+            Some(&Action::PlayCard(enemy_id, 0))
+        );
+    }
 
-            // Show the player input prompt
+    #[test]
+    fn test_multiple_targets_show_modal() {
+        let mut game = Game::new();
+        game.game_state.hand = game.game_state.instances(&[CardId::PHASERS]);
+        let enemy_id = game.game_state.enemy.unwrap();
+        let other_enemy_id = add_enemy(&mut game);
 
-            // Accumulate the list of cards in the hand with a number
-            // to press to play it
-            let mut cards_to_play = String::new();
-            for (idx, i) in game_state.hand.iter().enumerate() {
-                let name = game_state.cards.get(i).unwrap().name;
-                cards_to_play.push_str(&format!("[{}]{} ", idx + 1, name));
+        game.handle_keyboard_input(Key::Char('1'));
+
+        match &game.gui_state {
+            GuiState::TargetSelect(state) => {
+                assert_eq!(state.state.targets, vec![enemy_id, other_enemy_id]);
             }
+            _ => panic!("Expected the target select modal"),
+        }
+        assert!(game.game_state.actions.is_empty());
+
+        // Choosing the second target plays the card against it
+        game.handle_keyboard_input(Key::Char('2'));
+        game.update();
+        assert!(game.game_state.hand.is_empty());
+        assert_eq!(
+            game.game_state.entity_state[&other_enemy_id]
+                .get_state()
+                .get(&Attribute::Shields),
+            Some(&9)
+        );
+    }
 
-            let prompt = Paragraph::new(vec![
-                Spans::from("Select a card to play"),
-                Spans::from(Span::styled(
-                    cards_to_play,
-                    Style::default().fg(Color::LightGreen),
-                )),
-            ])
-            .block(Block::default().borders(Borders::ALL))
-            .alignment(Alignment::Center)
-            .wrap(Wrap { trim: false });
-
-            f.render_widget(prompt, chunks[3]);
-
-            if let GuiState::TargetSelect(state) = &game.gui_state {
-                // Create a centered modal
-                let chunks = Layout::default()
-                    .direction(Direction::Vertical)
-                    .constraints(
-                        [
-                            Constraint::Percentage(33),
-                            Constraint::Percentage(33),
-                            Constraint::Percentage(33),
-                        ]
-                            .as_ref(),
-                    )
-                    .split(f.size());
-
-                let horizontal_chunks = Layout::default()
-                    .direction(Direction::Horizontal)
-                    .constraints(
-                        [
-                            Constraint::Percentage(20),
-                            Constraint::Percentage(60),
-                            Constraint::Percentage(20),
-                        ]
-                            .as_ref(),
-                    )
-                    .split(chunks[1]);
-                let modal = horizontal_chunks[1];
-
-                // Clear it so the background is blank
-                f.render_widget(Clear, modal);
-
-                let mut targets = String::new();
-                for (idx, i) in state.state.targets.iter().enumerate() {
-                    let name = &*game_state.entity_state.get(i).unwrap().get_name();
-                    targets.push_str(&format!("[{}]{} ", idx + 1, name));
-                }
+    #[test]
+    fn test_ally_target_lists_allies() {
+        let mut game = Game::new();
+        game.game_state.cards.insert(Card {
+            id: CardId::new("repair"),
+            name: "Repair",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Ally,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 3);
+        let drone = Enemy {
+            name: String::from("Drone"),
+            state: s,
+            team: Team::Player,
+            ai: EnemyAi::Cycle(vec![]),
+            ai_step: 0,
+            art: None,
+        };
+        let drone_id = game.game_state.add_entity(None, Box::new(drone));
+        game.game_state.hand = game.game_state.instances(&[CardId::new("repair")]);
+
+        game.handle_keyboard_input(Key::Char('1'));
 
-                let prompt = Paragraph::new(vec![
-                    Spans::from("Select a target"),
-                    Spans::from(Span::styled(
-                        targets,
-                        Style::default().fg(Color::LightGreen),
-                    )),
-                ])
-                    .block(Block::default()
-                           .borders(Borders::ALL)
-                           .style(Style::default().bg(Color::Black)))
-                    .alignment(Alignment::Center)
-                    .wrap(Wrap { trim: false });
-
-                f.render_widget(prompt, modal);
+        match &game.gui_state {
+            GuiState::TargetSelect(state) => {
+                assert_eq!(state.state.targets, vec![game.game_state.player, drone_id]);
             }
-        })?;
+            _ => panic!("Expected the target select modal"),
+        }
+    }
+
+    /// Counts how many times the terminal would be restored
+    struct CountRestores(Rc<Cell<u32>>);
+
+    impl Restore for CountRestores {
+        fn restore(&mut self) -> io::Result<()> {
+            self.0.set(self.0.get() + 1);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_terminal_guard_restores_on_drop() {
+        let restores = Rc::new(Cell::new(0));
+        {
+            let _guard = TerminalGuard(CountRestores(restores.clone()));
+            assert_eq!(restores.get(), 0);
+        }
+        assert_eq!(restores.get(), 1);
+
+        // The terminal is also restored when a panic unwinds
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            let _guard = TerminalGuard(CountRestores(restores.clone()));
+            panic!("Boom");
+        }));
+        assert!(result.is_err());
+        assert_eq!(restores.get(), 2);
+    }
+
+    #[test]
+    fn test_victory_ends_combat() {
+        let mut game = Game::new();
+        let enemy_id = game.game_state.enemy.unwrap();
+        game.game_state.remove_entity(&enemy_id).unwrap();
+
+        game.update();
 
-        match events.next()? {
-            Event::Tick => game.update(),
-            Event::Input(Key::Char('q')) => {
+        match &game.gui_state {
+            GuiState::Rest(state) => assert_eq!(state.state.choice, RestChoice::Menu),
+            _ => panic!("Expected the rest screen"),
+        }
+    }
+
+    #[test]
+    fn test_run_progression() {
+        let mut game = Game::new();
+        let encounters = game.run.encounters.len();
+
+        for encounter in 1..=encounters {
+            for enemy_id in game.game_state.enemy_ids() {
+                game.game_state.remove_entity(&enemy_id).unwrap();
+            }
+            game.update();
+            if encounter == encounters {
                 break;
-            },
-            Event::Input(input) => game.handle_keyboard_input(input),
+            }
+
+            // Rest by repairing then fight the next encounter
+            assert!(matches!(game.gui_state, GuiState::Rest(_)));
+            game.handle_keyboard_input(Key::Char('1'));
+            let names: Vec<&str> = game
+                .game_state
+                .enemy_ids()
+                .iter()
+                .map(|id| game.game_state.entity(id).unwrap().name())
+                .collect();
+            let expected: Vec<&str> =
+                game.run.encounters[encounter].enemies.iter().map(|e| e.name).collect();
+            assert_eq!(names, expected);
+        }
+
+        // Winning the last encounter wins the run
+        match &game.gui_state {
+            GuiState::GameOver(state) => assert_eq!(state.state.outcome, Outcome::Victory),
+            _ => panic!("Expected the game over screen"),
+        }
+        game.update();
+        assert!(matches!(game.gui_state, GuiState::GameOver(_)));
+    }
+
+    #[test]
+    fn test_rest_remove_card() {
+        let mut game = Game::new();
+        let enemy_id = game.game_state.enemy.unwrap();
+        game.game_state.remove_entity(&enemy_id).unwrap();
+        game.update();
+        let deck_size = game.game_state.master_deck.len();
+        let removed = game.game_state.master_deck[1].instance_id;
+
+        game.handle_keyboard_input(Key::Char('3'));
+        game.handle_keyboard_input(Key::Down);
+        game.handle_keyboard_input(Key::Char('\n'));
+        game.update();
+
+        // The next combat starts without the card
+        assert!(matches!(game.gui_state, GuiState::Combat(_)));
+        assert_eq!(game.game_state.master_deck.len(), deck_size - 1);
+        assert!(game.game_state.master_deck.iter().all(|card| card.instance_id != removed));
+        assert_eq!(game.game_state.hand.len() + game.game_state.draw.len(), deck_size - 1);
+    }
+
+    #[test]
+    fn test_concede() {
+        let mut game = Game::new();
+        game.game_state.hand = game.game_state.instances(&[CardId::SHIELDS]);
+        game.handle_keyboard_input(Key::Char('1'));
+        game.update();
+        game.game_state.phase = TurnPhase::PlayerInput;
+
+        game.handle_keyboard_input(game.keys.concede);
+        game.update();
+
+        match &game.gui_state {
+            GuiState::GameOver(state) => assert_eq!(state.state.outcome, Outcome::Conceded),
+            _ => panic!("Expected the game over screen"),
+        }
+        // The player is still alive but the run is over
+        assert!(game.game_state.is_alive(&game.game_state.player));
+        let stats = &game.game_state.stats;
+        assert_eq!(stats.turns_taken, 1);
+        assert_eq!(stats.cards_played, 1);
+    }
+
+    #[test]
+    fn test_filter_pile_view() {
+        let mut game = Game::new();
+        game.handle_keyboard_input(Key::Char('v'));
+
+        // Keys that normally close the modal are typed into the query
+        for key in [Key::Char('/'), Key::Char('v'), Key::Char('q'), Key::Backspace].iter() {
+            game.handle_keyboard_input(*key);
+        }
+        game.handle_keyboard_input(Key::Char('p'));
+        match &game.gui_state {
+            GuiState::ViewPile(state) => {
+                assert_eq!(state.state.query, "vp");
+                assert!(state.state.typing);
+            }
+            _ => panic!("Expected the pile modal"),
+        }
+
+        // The query is kept when done typing and moving to the next pile
+        game.handle_keyboard_input(Key::Char('\n'));
+        game.handle_keyboard_input(Key::Char('\t'));
+        match &game.gui_state {
+            GuiState::ViewPile(state) => {
+                assert_eq!(state.state.pile, PileKind::Discard);
+                assert_eq!(state.state.query, "vp");
+                assert!(!state.state.typing);
+            }
+            _ => panic!("Expected the pile modal"),
+        }
+
+        // Escape clears the query
+        game.handle_keyboard_input(Key::Char('/'));
+        game.handle_keyboard_input(Key::Esc);
+        match &game.gui_state {
+            GuiState::ViewPile(state) => assert!(state.state.query.is_empty()),
+            _ => panic!("Expected the pile modal"),
+        }
+
+        game.handle_keyboard_input(Key::Char('v'));
+        assert!(matches!(game.gui_state, GuiState::Combat(_)));
+    }
+
+    #[test]
+    fn test_remapped_end_turn() {
+        let mut game = Game::new();
+        game.keys.end_turn = Key::Char('n');
+        game.game_state.actions.clear();
+        // Skip confirming the end of the turn
+        game.game_state.energy = 0;
+
+        // The default key no longer ends the turn
+        game.handle_keyboard_input(Key::Char('e'));
+        assert!(game.game_state.actions.is_empty());
+
+        game.handle_keyboard_input(Key::Char('n'));
+        assert_eq!(game.game_state.actions.front(), Some(&Action::EndTurn));
+    }
+
+    #[test]
+    fn test_seed_option() {
+        let options = Options {
+            seed: Some(7),
+            cards: None,
         };
+        let hand = |game: Game| -> Vec<CardId> {
+            game.game_state.hand.iter().map(|card| card.card_id).collect()
+        };
+
+        // The same seed deals the same opening hand
+        let first = hand(Game::with_options(&options).unwrap());
+        assert_eq!(hand(Game::with_options(&options).unwrap()), first);
     }
 
-    Ok(())
-}
+    #[test]
+    fn test_card_tags() {
+        let game_state = Game::init_state(&[Game::battleship()], Game::cards(), None).unwrap();
+        let shields = game_state.cards.get(&CardId::SHIELDS).unwrap();
+        let phasers = game_state.cards.get(&CardId::PHASERS).unwrap();
+        assert!(shields.has_tag(CardTag::Skill));
+        assert!(!shields.has_tag(CardTag::Attack));
+        assert!(phasers.has_tag(CardTag::Attack));
+
+        let powers = game_state.cards.with_tag(CardTag::Power);
+        assert_eq!(powers, vec![CardId::AUTO_SHIELDS]);
+    }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    panic::set_hook(Box::new(|info| {
-        panic_hook(info);
-    }));
+    #[test]
+    fn test_number_keys_follow_hand_order() {
+        let mut game = Game::new();
+        game.game_state.hand = game.game_state.instances(&[CardId::SHIELDS, CardId::PHASERS]);
+        assert_eq!(game.hand_index('1'), Some(0));
+
+        // Phasers is shown first when sorted by name so shields are
+        // played by pressing 2
+        game.hand_order = HandOrder::Name;
+        assert_eq!(game.hand_index('1'), Some(1));
+        assert_eq!(game.hand_index('2'), Some(0));
+        assert_eq!(game.hand_index('3'), None);
+        assert_eq!(game.hand_index('0'), None);
+
+        game.game_state.actions.clear();
+        game.handle_keyboard_input(Key::Char('2'));
+        assert_eq!(
+            game.game_state.actions.front(),
+            Some(&Action::PlayCard(game.game_state.player, 0))
+        );
+    }
 
-    run()
+    #[test]
+    fn test_typing_quit_key() {
+        let mut game = Game::new();
+        let mut inputs = InputBuffer::new();
+        let quit = game.keys.quit;
+        let mut send = |game: &mut Game, key: Key| {
+            game.handle_event(Event::Input(key), &mut inputs, Duration::from_millis(250))
+        };
+
+        assert!(send(&mut game, Key::Char('v')));
+        assert!(send(&mut game, Key::Char('/')));
+        assert!(game.is_typing());
+
+        // The quit key is typed into the filter instead of quitting
+        assert!(send(&mut game, quit));
+        match &game.gui_state {
+            GuiState::ViewPile(state) => assert_eq!(state.state.query, "q"),
+            _ => panic!("Expected the pile modal"),
+        }
+
+        // And quits again once done typing
+        assert!(send(&mut game, Key::Char('\n')));
+        assert!(!game.is_typing());
+        assert!(!send(&mut game, quit));
+    }
+
+    #[test]
+    fn test_leave_modal_without_enemies() {
+        let mut game = Game::new();
+        let combat = GuiStateMachine::<Combat>::new();
+        game.gui_state = GuiState::Scry(GuiStateMachine::<Scry>::transition_from(&combat, ()));
+        for enemy_id in game.game_state.enemy_ids() {
+            game.game_state.remove_entity(&enemy_id).unwrap();
+        }
+        assert_eq!(game.game_state.enemy, None);
+
+        // Closing the scry modal after the last enemy is destroyed
+        // goes on to rest
+        game.update();
+        assert!(matches!(game.gui_state, GuiState::Rest(_)));
+    }
 }
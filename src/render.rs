@@ -0,0 +1,1112 @@
+//! Builds the widgets for each part of the screen from the game
+//! state so the draw loop only has to lay them out.
+use std::time::Duration;
+
+use tui::{
+    backend::Backend,
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::Style,
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, List, ListItem, Paragraph, Wrap},
+    Frame,
+};
+
+use crate::engine::*;
+use crate::gui::{
+    filter_pile, HandOrder, Panel, PileKind, Rest, RestChoice, TargetSelect, ViewPile,
+};
+use crate::keys::{key_name, Key};
+use crate::theme::Theme;
+
+/// Art for the battleship enemy
+pub const BATTLESHIP_ART: &str = "
+                           |-----------|
+           i               |===========|
+           |               |,---------.|                      __--~\\__--.
+    #---,'----`-_   `n     |`---------'|    `n    `n     ,--~~  __-/~~--'_____.
+       |~~~~~~~~~|---~---/=|___________|=\\---~-----~-----| .--~~  |  .__|     |
+     -[|.--_. ===|#####|-| |@@@@|+-+@@@| |]=###|/-++++-[| ||||___+_.  | `===='-.
+     -[|'==~'    |#####|-| |@@@@|+-+@@@| |]=###|\\-++++-[| ||||~~~+~'  | ,====.-'
+       |_________|---u---\\=|~~~~~~~~~~~|=/---u-----u-----| '--__  |  '~~|     |
+        \\       /=-   `    |,---------.|      `     `    `--__  ~~-\\__--.~~~~~'
+----=:===\\     /           |`---------'|                      ~~--_/~~--'
+      --<:\\___/--          |===========|
+                           |-----------|
+                           |___________|";
+
+/// Art for a small drone
+pub const DRONE_ART: &str = "
+      _____
+  ---/ o o \\---
+      \\___/";
+
+/// Drawn for enemies without art of their own
+const FALLBACK_ART: &str = "
+        __
+   ____/  \\____
+  <____ ?? ____>
+       \\__/";
+
+/// A bordered block in the theme's border style
+fn block(theme: &Theme) -> Block<'static> {
+    Block::default()
+        .borders(Borders::ALL)
+        .border_style(theme.border_style())
+}
+
+/// Mark the title of the panel currently selected for scrolling
+fn panel_title(title: &str, panel: Panel, selected: Panel) -> String {
+    if panel == selected {
+        format!("{} *", title)
+    } else {
+        title.to_string()
+    }
+}
+
+/// Hull below this percent of the max hull is critical
+const CRITICAL_HULL_PERCENT: i32 = 25;
+
+/// Every attribute in the state in display order separated by
+/// slashes. Max hull and max shields are shown as part of the hull and
+/// shields.
+pub fn format_state(state: &State) -> String {
+    let mut attributes: Vec<(&Attribute, &i32)> = state
+        .iter()
+        .filter(|(k, _)| **k != Attribute::MaxHull && **k != Attribute::MaxShields)
+        .collect();
+    attributes.sort();
+
+    attributes
+        .iter()
+        .map(|(k, v)| {
+            let max = match k {
+                Attribute::Hull => state.get(&Attribute::MaxHull),
+                Attribute::Shields => state.get(&Attribute::MaxShields),
+                _ => None,
+            };
+            match max {
+                Some(max) => format!("{}: {}/{}", k, v, max),
+                None => format!("{}: {}", k, v),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("  /  ")
+}
+
+/// Icon shown for an attribute that's a status or `None` for the
+/// attributes shown in full by `format_state`
+pub fn status_icon(attribute: Attribute) -> Option<&'static str> {
+    match attribute {
+        Attribute::Block => Some("▣"),
+        Attribute::DeflectorField => Some("⚡"),
+        Attribute::Strength => Some("⚔"),
+        Attribute::Dexterity => Some("✦"),
+        Attribute::Evasion => Some("≈"),
+        Attribute::Shields | Attribute::MaxShields | Attribute::Hull | Attribute::MaxHull => None,
+    }
+}
+
+/// Every status the entity has as an icon and amount in display order
+/// e.g. "⚡2 ⚔1"
+pub fn format_statuses(entity: &dyn Entity) -> String {
+    let state = entity.get_state();
+    Attribute::ALL
+        .iter()
+        .filter_map(|attribute| {
+            let icon = status_icon(*attribute)?;
+            match state.get(attribute) {
+                Some(amount) if *amount != 0 => Some(format!("{}{}", icon, amount)),
+                _ => None,
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// The entity's hull and shields followed by its statuses
+fn format_entity(entity: &dyn Entity) -> String {
+    let vitals: State = entity
+        .get_state()
+        .iter()
+        .filter(|(attribute, _)| status_icon(**attribute).is_none())
+        .map(|(attribute, amount)| (*attribute, *amount))
+        .collect();
+    let statuses = format_statuses(entity);
+    if statuses.is_empty() {
+        format_state(&vitals)
+    } else {
+        format!("{}  {}", format_state(&vitals), statuses)
+    }
+}
+
+/// Whether the hull has dropped below the critical threshold of the
+/// max hull
+pub fn is_critical(state: &State) -> bool {
+    match (state.get(&Attribute::Hull), state.get(&Attribute::MaxHull)) {
+        (Some(hull), Some(max_hull)) => hull * 100 < max_hull * CRITICAL_HULL_PERCENT,
+        _ => false,
+    }
+}
+
+/// Show how each attribute that differs between the states would
+/// change e.g. "Shields: 10→7"
+pub fn format_preview(before: &State, after: &State) -> String {
+    let mut changes: Vec<(&Attribute, &i32)> = after
+        .iter()
+        .filter(|(k, v)| before.get(k).copied().unwrap_or(0) != **v)
+        .collect();
+    changes.sort();
+
+    changes
+        .iter()
+        .map(|(k, v)| format!("{}: {}→{}", k, before.get(k).copied().unwrap_or(0), v))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Describe what an enemy will do next turn. Attacks show how much
+/// will get through the target's shields e.g. "Attack 8 → 3 through".
+pub fn format_intent(intent: EnemyIntent, target: &State) -> String {
+    match intent {
+        EnemyIntent::Attack(amount) => {
+            format!("Attack {} → {} through", amount, mitigated_damage(target, amount))
+        }
+        EnemyIntent::Defend(amount) => format!("Defend {}", amount),
+        EnemyIntent::Escalate { base, per_turn } => {
+            format!("Attack {} + {} per turn", base, per_turn)
+        }
+    }
+}
+
+/// Describe an event for the combat log
+pub fn describe_event(game_state: &GameState, event: &GameEvent) -> String {
+    match event {
+        GameEvent::DeckReshuffled => String::from("Discard pile shuffled into draw pile"),
+        GameEvent::CardPlayed { card_id, target } => {
+            let card_name = game_state.cards.get(card_id).map_or("Unknown card", |c| c.name);
+            match game_state.entity_state.get(target) {
+                Some(entity) => format!("Played {} on {}", card_name, entity.name()),
+                None => format!("Played {}", card_name),
+            }
+        }
+        GameEvent::EnemyActed { enemy, intent } => {
+            let name = game_state.entity_state.get(enemy).map_or("Enemy", |e| e.name());
+            match intent {
+                EnemyIntent::Attack(amount) => format!("{} attacked for {}", name, amount),
+                EnemyIntent::Escalate { .. } => format!("{} attacked", name),
+                EnemyIntent::Defend(amount) => {
+                    format!("{} is defending (+{} Shields)", name, amount)
+                }
+            }
+        }
+        GameEvent::AbilityUsed { enemy, ability } => {
+            let name = game_state.entity_state.get(enemy).map_or("Enemy", |e| e.name());
+            format!("{} used {}", name, ability)
+        }
+        GameEvent::EntityDestroyed { .. } => String::from("Target destroyed"),
+        GameEvent::Fatigue { damage } => format!("Fatigue deals {} damage", damage),
+        GameEvent::BlockExpired { entity, amount } => {
+            let name = game_state.entity_state.get(entity).map_or("Unknown", |e| e.name());
+            format!("{} lost {} block", name, amount)
+        }
+        GameEvent::OverdrawBurned { card } => {
+            let card_name = game_state.cards.get(card).map_or("Unknown card", |c| c.name);
+            format!("Hand full, {} burned", card_name)
+        }
+    }
+}
+
+/// Elapsed time as minutes, seconds and tenths e.g. 1:05.3
+pub fn format_clock(elapsed: Duration) -> String {
+    let tenths = elapsed.as_millis() / 100;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+/// The speedrun clock right aligned on a single line
+pub fn build_clock<'a>(elapsed: Duration, theme: &Theme) -> Paragraph<'a> {
+    Paragraph::new(Spans::from(format_clock(elapsed)))
+        .style(Style::default().fg(theme.text))
+        .alignment(Alignment::Right)
+}
+
+/// The player's status bar
+pub fn build_status_paragraph<'a>(game_state: &GameState, theme: &Theme) -> Paragraph<'a> {
+    let player = game_state.entity(&game_state.player).ok();
+    let player_state = player.map(|player| player.get_state());
+
+    let status = match player {
+        Some(player) => format!("Turn {}  /  {}", game_state.turn, format_entity(player)),
+        None => format!("Turn {}", game_state.turn),
+    };
+    let style = if player_state.is_some_and(is_critical) {
+        Style::default().fg(theme.critical)
+    } else {
+        Style::default()
+    };
+
+    Paragraph::new(Span::raw(status))
+        .block(block(theme).title("Status"))
+        .style(style)
+        .alignment(Alignment::Center)
+}
+
+/// The enemy ship and its status
+pub fn build_enemy_paragraph<'a>(game_state: &GameState, theme: &Theme) -> Paragraph<'a> {
+    let enemy = match game_state.enemy.and_then(|id| game_state.entity(&id).ok()) {
+        Some(enemy) => enemy,
+        None => {
+            return Paragraph::new("No enemies remaining")
+                .block(block(theme))
+                .style(Style::default().fg(theme.text))
+        }
+    };
+
+    let art = enemy.art().unwrap_or(FALLBACK_ART);
+    let mut text: Vec<Spans> = art.split('\n').map(Spans::from).collect();
+    text.push(Spans::from(""));
+    text.push(Spans::from(format_entity(enemy)));
+
+    let intent = enemy.peek_intent().map(|i| i.for_turn(game_state.turn));
+    if let (Some(intent), Some(player)) = (intent, game_state.player_state()) {
+        text.push(Spans::from(format!("Intent: {}", format_intent(intent, player))));
+    }
+
+    // Flash the enemy while the last card played animates
+    let enemy_color = match game_state.phase {
+        TurnPhase::Animating(frames_left) if frames_left % 2 == 1 => theme.flash,
+        _ => theme.ship,
+    };
+
+    Paragraph::new(text)
+        .block(block(theme))
+        .style(Style::default().fg(enemy_color))
+        .alignment(Alignment::Left)
+}
+
+/// Every enemy's intent in the order they act when the turn ends. The
+/// enemy about to act first is highlighted.
+pub fn build_turn_order<'a>(game_state: &GameState, theme: &Theme) -> Paragraph<'a> {
+    let player_state = game_state.player_state();
+    let mut spans = vec![];
+    for (idx, enemy_id) in game_state.enemy_turn_order().iter().enumerate() {
+        let enemy = match game_state.entity(enemy_id) {
+            Ok(enemy) => enemy,
+            Err(_) => continue,
+        };
+        let intent = enemy.peek_intent().map(|i| i.for_turn(game_state.turn));
+        let intent = match (intent, player_state) {
+            (Some(intent), Some(player_state)) => format_intent(intent, player_state),
+            _ => String::from("?"),
+        };
+        let text = format!("{}. {}: {}", idx + 1, enemy.name(), intent);
+
+        if idx == 0 {
+            spans.push(Span::styled(text, theme.highlight_style()));
+        } else {
+            spans.push(Span::raw("  /  "));
+            spans.push(Span::raw(text));
+        }
+    }
+
+    Paragraph::new(Spans::from(spans))
+        .block(block(theme).title("Turn Order"))
+        .style(Style::default().fg(theme.text))
+}
+
+/// The combat log with the most recent events first
+pub fn build_log_list<'a>(
+    game_state: &GameState,
+    offset: usize,
+    selected: Panel,
+    theme: &Theme,
+) -> List<'a> {
+    let mut items: Vec<ListItem> = game_state
+        .events
+        .iter()
+        .rev()
+        .skip(offset)
+        .map(|e| ListItem::new(Span::raw(describe_event(game_state, e))))
+        .collect();
+
+    // Keep the warning at the top of the log while the hull is critical
+    let player_critical = game_state.player_state().is_some_and(is_critical);
+    if player_critical {
+        let warning = Span::styled("HULL CRITICAL", Style::default().fg(theme.critical));
+        items.insert(0, ListItem::new(warning));
+    }
+
+    List::new(items)
+        .block(block(theme).title(panel_title("Log", Panel::Log, selected)))
+        .style(Style::default().fg(theme.text))
+}
+
+pub fn build_draw_pile(theme: &Theme) -> Block<'static> {
+    block(theme).title("Draw")
+}
+
+/// How many of each card are in the deck across every pile
+pub fn build_deck_stats<'a>(game_state: &GameState, theme: &Theme) -> List<'a> {
+    let mut counts: Vec<(&str, usize)> = game_state
+        .deck_composition()
+        .iter()
+        .map(|(card_id, count)| {
+            let name = game_state.cards.get(card_id).map_or(card_id.as_str(), |c| c.name);
+            (name, *count)
+        })
+        .collect();
+    counts.sort();
+
+    let items: Vec<ListItem> = counts
+        .into_iter()
+        .map(|(name, count)| ListItem::new(Span::raw(format!("{}: {}", name, count))))
+        .collect();
+
+    List::new(items)
+        .block(block(theme).title("Deck"))
+        .style(Style::default().fg(theme.text))
+}
+
+/// What each attribute means
+pub fn build_legend<'a>(theme: &Theme) -> Paragraph<'a> {
+    let lines: Vec<Spans> = Attribute::ALL
+        .iter()
+        .map(|attribute| {
+            let icon = status_icon(*attribute).map_or(String::new(), |i| format!("{} ", i));
+            Spans::from(format!("{}{}: {}", icon, attribute, attribute.description()))
+        })
+        .collect();
+
+    Paragraph::new(lines)
+        .block(block(theme).title("Legend"))
+        .style(Style::default().fg(theme.text))
+        .wrap(Wrap { trim: false })
+}
+
+pub fn build_hand_list<'a>(game_state: &GameState, order: HandOrder, theme: &Theme) -> List<'a> {
+    let items: Vec<ListItem> = order
+        .hand_indices(game_state)
+        .into_iter()
+        .map(|idx| {
+            let name = game_state.cards.get(&game_state.hand[idx].card_id).unwrap().name;
+            let cost = game_state.card_cost(idx).unwrap_or(0);
+            let text = format!("{} ({})", name, cost);
+            if game_state.is_unplayable(idx) {
+                ListItem::new(Span::styled(text, Style::default().fg(theme.disabled)))
+            } else {
+                ListItem::new(Span::raw(text))
+            }
+        })
+        .collect();
+    let title = format!(
+        "Hand by {}  /  Energy: {}/{}",
+        order.label(),
+        game_state.energy,
+        game_state.max_energy
+    );
+
+    List::new(items)
+        .block(block(theme).title(title))
+        .style(Style::default().fg(theme.text))
+        .highlight_style(theme.highlight_style())
+        .highlight_symbol(">>")
+}
+
+/// The discard pile with the most recently discarded card first
+pub fn build_discard_list<'a>(
+    game_state: &GameState,
+    offset: usize,
+    selected: Panel,
+    theme: &Theme,
+) -> List<'a> {
+    let items: Vec<ListItem> = game_state
+        .discard
+        .iter()
+        .rev()
+        .skip(offset)
+        .map(|i| ListItem::new(game_state.cards.get(&i.card_id).unwrap().name))
+        .collect();
+
+    List::new(items)
+        .block(block(theme).title(panel_title("Discard", Panel::Discard, selected)))
+        .style(Style::default().fg(theme.text))
+        .highlight_style(theme.highlight_style())
+        .highlight_symbol(">>")
+}
+
+/// What the player is asked to do given the actions available to
+/// them right now. `end_turn` is the key that ends the turn.
+pub fn prompt_text(game_state: &GameState, end_turn: Key) -> String {
+    if game_state.legal_actions() != vec![Action::EndTurn] {
+        String::from("Select a card to play")
+    } else if game_state.hand.is_empty() {
+        format!("No cards to play. Press [{}] to end turn.", key_name(end_turn))
+    } else {
+        format!("No playable cards. Press [{}] to end turn.", key_name(end_turn))
+    }
+}
+
+/// The prompt listing the number to press to play each card with
+/// an optional notice for the player
+pub fn build_prompt<'a>(
+    game_state: &GameState,
+    notice: Option<&str>,
+    order: HandOrder,
+    end_turn: Key,
+    theme: &Theme,
+) -> Paragraph<'a> {
+    let mut cards_to_play = String::new();
+    for (idx, hand_idx) in order.hand_indices(game_state).into_iter().enumerate() {
+        let name = game_state.cards.get(&game_state.hand[hand_idx].card_id).unwrap().name;
+        cards_to_play.push_str(&format!("[{}]{} ", idx + 1, name));
+    }
+
+    Paragraph::new(vec![
+        Spans::from(prompt_text(game_state, end_turn)),
+        Spans::from(Span::styled(cards_to_play, Style::default().fg(theme.prompt))),
+    ])
+    .block(block(theme).title(notice.unwrap_or("").to_string()))
+    .alignment(Alignment::Center)
+    .wrap(Wrap { trim: false })
+}
+
+/// A centered area for showing a modal over the screen
+fn modal_area(area: Rect) -> Rect {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            [
+                Constraint::Percentage(33),
+                Constraint::Percentage(33),
+                Constraint::Percentage(33),
+            ]
+            .as_ref(),
+        )
+        .split(area);
+
+    let horizontal_chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(
+            [
+                Constraint::Percentage(20),
+                Constraint::Percentage(60),
+                Constraint::Percentage(20),
+            ]
+            .as_ref(),
+        )
+        .split(chunks[1]);
+
+    horizontal_chunks[1]
+}
+
+/// Draw a modal with the given lines of text centered over the rest
+/// of the screen
+pub fn render_modal<B: Backend>(f: &mut Frame<B>, title: &str, lines: Vec<Spans>, theme: &Theme) {
+    let area = modal_area(f.size());
+
+    let modal = Paragraph::new(lines)
+        .block(
+            block(theme)
+                .title(title.to_string())
+                .style(Style::default().bg(theme.modal)),
+        )
+        .alignment(Alignment::Center)
+        .wrap(Wrap { trim: false });
+
+    // Clear it so the background is blank
+    f.render_widget(Clear, area);
+    f.render_widget(modal, area);
+}
+
+/// Each target along with what the card would do to it
+pub fn target_modal_lines<'a>(
+    state: &TargetSelect,
+    game_state: &GameState,
+    theme: &Theme,
+) -> Vec<Spans<'a>> {
+    let card_id = game_state.hand[state.card_idx as usize].card_id;
+    let mut targets = vec![];
+    for (idx, i) in state.targets.iter().enumerate() {
+        // Targets destroyed since the modal opened are skipped
+        let entity = match game_state.entity(i) {
+            Ok(entity) => entity,
+            Err(_) => continue,
+        };
+        let name = entity.name();
+        let preview = game_state
+            .card(&card_id)
+            .map(|card| preview_apply(game_state, *i, &card.effects))
+            .map(|after| format_preview(entity.get_state(), &after))
+            .unwrap_or_default();
+        let text = format!("[{}]{} ({}) ", idx + 1, name, preview);
+        // Highlight the target Enter would choose
+        let style = if idx == state.selected_target_idx {
+            theme.highlight_style()
+        } else {
+            Style::default().fg(theme.prompt)
+        };
+        targets.push(Span::styled(text, style));
+    }
+
+    vec![Spans::from("Select a target"), Spans::from(targets)]
+}
+
+/// The cards in the discard pile that can be returned to the hand
+pub fn salvage_modal_lines<'a>(game_state: &GameState, theme: &Theme) -> Vec<Spans<'a>> {
+    let mut cards = String::new();
+    for (idx, i) in game_state.discard.iter().rev().enumerate() {
+        let name = game_state.cards.get(&i.card_id).unwrap().name;
+        cards.push_str(&format!("[{}]{} ", idx + 1, name));
+    }
+
+    vec![
+        Spans::from("Discard pile"),
+        Spans::from(Span::styled(cards, Style::default().fg(theme.prompt))),
+        Spans::from(""),
+        Spans::from("Select a card to return to your hand"),
+    ]
+}
+
+pub fn confirm_end_turn_lines<'a>(game_state: &GameState, theme: &Theme) -> Vec<Spans<'a>> {
+    vec![
+        Spans::from(format!("You have {} energy left and cards you can play", game_state.energy)),
+        Spans::from(""),
+        Spans::from(Span::styled("[y]End turn [n]Keep playing", Style::default().fg(theme.prompt))),
+    ]
+}
+
+/// The cards in a pile. The draw pile is sorted by name so viewing it
+/// doesn't give away what's drawn next.
+pub fn pile_modal_lines<'a>(
+    game_state: &GameState,
+    state: &ViewPile,
+    theme: &Theme,
+) -> Vec<Spans<'a>> {
+    let pile = state.pile;
+    let (title, cards) = match pile {
+        PileKind::Draw => ("Draw pile", &game_state.draw),
+        PileKind::Discard => ("Discard pile", &game_state.discard),
+        PileKind::Exhaust => ("Exhaust pile", &game_state.exhaust),
+    };
+    let mut names: Vec<&str> = filter_pile(&game_state.cards, cards, &state.query)
+        .iter()
+        .map(|card| {
+            let card_id = &card.card_id;
+            game_state.cards.get(card_id).map_or(card_id.as_str(), |c| c.name)
+        })
+        .collect();
+    if pile == PileKind::Draw {
+        names.sort();
+    }
+
+    let contents = if names.is_empty() {
+        String::from("Empty")
+    } else {
+        names.join(", ")
+    };
+
+    let count = if names.len() == cards.len() {
+        names.len().to_string()
+    } else {
+        format!("{} of {}", names.len(), cards.len())
+    };
+    let (filter, help) = if state.typing {
+        (format!("Filter: {}_", state.query), "[Enter]Done [Esc]Clear")
+    } else {
+        (format!("Filter: {}", state.query), "[Tab]Next pile [/]Filter [v]Close")
+    };
+
+    vec![
+        Spans::from(format!("{} ({})", title, count)),
+        Spans::from(Span::styled(contents, Style::default().fg(theme.prompt))),
+        Spans::from(filter),
+        Spans::from(help),
+    ]
+}
+
+/// What each enemy will do this turn and next
+pub fn forecast_lines<'a>(game_state: &GameState, theme: &Theme) -> Vec<Spans<'a>> {
+    let no_state = State::new();
+    let player_state = game_state.player_state().unwrap_or(&no_state);
+    let mut lines = vec![];
+    for (enemy_id, intents) in game_state.forecast_intents(2) {
+        let name = game_state.entity(&enemy_id).map_or("Enemy", |e| e.name());
+        let intents: Vec<String> = intents
+            .into_iter()
+            .map(|intent| format_intent(intent, player_state))
+            .collect();
+        let text = match intents.as_slice() {
+            [] => String::from("Nothing planned"),
+            [now] => now.clone(),
+            [now, next, ..] => format!("{}, then {}", now, next),
+        };
+        lines.push(Spans::from(vec![
+            Span::raw(format!("{}: ", name)),
+            Span::styled(text, Style::default().fg(theme.prompt)),
+        ]));
+    }
+
+    lines.push(Spans::from(""));
+    lines.push(Spans::from("Random intents can change [f]Close"));
+    lines
+}
+
+/// The cards revealed from the top of the draw pile
+pub fn scry_modal_lines<'a>(game_state: &GameState, theme: &Theme) -> Vec<Spans<'a>> {
+    let mut cards = String::new();
+    for (idx, i) in game_state.scry.iter().enumerate() {
+        let name = game_state.cards.get(i).unwrap().name;
+        cards.push_str(&format!("[{}]{} ", idx + 1, name));
+    }
+
+    vec![
+        Spans::from("Top of the draw pile"),
+        Spans::from(Span::styled(cards, Style::default().fg(theme.prompt))),
+        Spans::from(""),
+        Spans::from("Select a card to discard or press [Enter] to keep the rest"),
+    ]
+}
+
+/// The screen for choosing what to do while resting between combats.
+/// `cancel` is the key that goes back to the menu.
+pub fn build_rest<'a>(
+    game_state: &GameState,
+    state: &Rest,
+    cancel: Key,
+    theme: &Theme,
+) -> Paragraph<'a> {
+    let prompt = Style::default().fg(theme.prompt);
+    let mut lines = vec![Spans::from("The enemy fleet was destroyed"), Spans::from("")];
+
+    match state.choice {
+        RestChoice::Menu => {
+            lines.push(Spans::from(format!(
+                "Hull: {}/{}",
+                game_state.attribute(&game_state.player, Attribute::Hull),
+                game_state.attribute(&game_state.player, Attribute::MaxHull)
+            )));
+            lines.push(Spans::from(""));
+            lines.push(Spans::from(Span::styled(
+                "[1]Repair hull [2]Upgrade a card [3]Remove a card",
+                prompt,
+            )));
+        }
+        RestChoice::Upgrade | RestChoice::Remove => {
+            let action = if state.choice == RestChoice::Upgrade { "upgrade" } else { "remove" };
+            lines.push(Spans::from(format!("Select a card to {}", action)));
+            lines.push(Spans::from(""));
+            for (idx, card) in game_state.master_deck.iter().enumerate() {
+                let card_id = &card.card_id;
+                let name = game_state.cards.get(card_id).map_or(card_id.as_str(), |c| c.name);
+                let upgraded = if card.mods.upgraded { "+" } else { "" };
+                let line = format!("{}{}", name, upgraded);
+                if idx == state.selected {
+                    let highlighted = Span::styled(format!(">>{}", line), theme.highlight_style());
+                    lines.push(Spans::from(highlighted));
+                } else {
+                    lines.push(Spans::from(line));
+                }
+            }
+            lines.push(Spans::from(""));
+            let help = format!("[Up/Down]Select [Enter]Confirm [{}]Back", key_name(cancel));
+            lines.push(Spans::from(Span::styled(help, prompt)));
+        }
+    }
+
+    Paragraph::new(lines)
+        .block(block(theme).title("Rest"))
+        .alignment(Alignment::Center)
+}
+
+/// The screen shown when the combat is over
+pub fn build_game_over<'a>(
+    outcome: Outcome,
+    stats: &RunStats,
+    quit: Key,
+    theme: &Theme,
+) -> Paragraph<'a> {
+    let title = match outcome {
+        Outcome::Victory => "Victory!",
+        Outcome::Defeat => "Your ship was destroyed",
+        Outcome::Conceded => "You abandoned the fight",
+    };
+    let quit = format!("[{}]Quit", key_name(quit));
+
+    Paragraph::new(vec![
+        Spans::from(title),
+        Spans::from(""),
+        Spans::from(format!("Damage dealt: {}", stats.damage_dealt)),
+        Spans::from(format!("Cards played: {}", stats.cards_played)),
+        Spans::from(format!("Turns taken: {}", stats.turns_taken)),
+        Spans::from(format!("Enemies destroyed: {}", stats.enemies_destroyed)),
+        Spans::from(""),
+        Spans::from(Span::styled(quit, Style::default().fg(theme.prompt))),
+    ])
+    .block(block(theme).title("Game Over"))
+    .alignment(Alignment::Center)
+}
+
+#[cfg(test)]
+mod test_render {
+    use super::*;
+    use crate::gui::GuiStateMachine;
+    use tui::{backend::TestBackend, buffer::Buffer, widgets::Widget, Terminal};
+
+    /// Render a widget and return the text of each line
+    fn render_lines<W: Widget>(widget: W, width: u16, height: u16) -> Vec<String> {
+        let area = Rect::new(0, 0, width, height);
+        let mut buffer = Buffer::empty(area);
+        widget.render(area, &mut buffer);
+
+        buffer
+            .content()
+            .chunks(width as usize)
+            .map(|cells| cells.iter().map(|c| c.symbol.as_str()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_status_paragraph() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 7);
+        s.insert(Attribute::Shields, 3);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+        game.turn = 2;
+
+        let lines = render_lines(build_status_paragraph(&game, &Theme::default()), 40, 3);
+        assert_eq!(
+            lines,
+            vec![
+                "┌Status────────────────────────────────┐",
+                "│   Turn 2  /  Shields: 3  /  Hull: 7  │",
+                "└──────────────────────────────────────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_entities_render() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        game.turn = 3;
+
+        let lines = render_lines(build_status_paragraph(&game, &Theme::default()), 20, 3);
+        assert_eq!(lines[1], format!("│{:^18}│", "Turn 3"));
+        let lines = render_lines(build_enemy_paragraph(&game, &Theme::default()), 30, 3);
+        assert_eq!(lines[1], format!("│{:28}│", "No enemies remaining"));
+    }
+
+    #[test]
+    fn test_enemy_paragraph_art() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        let drone = EnemyTemplate {
+            name: "Drone",
+            hull: 3,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: Some(DRONE_ART),
+        };
+        spawn_enemies(&mut game, &[drone]);
+
+        let lines = render_lines(build_enemy_paragraph(&game, &Theme::default()), 30, 8);
+        assert_eq!(lines[2], format!("│{:28}│", "      _____"));
+        assert_eq!(lines[3], format!("│{:28}│", "  ---/ o o \\---"));
+
+        // Enemies without art use the fallback
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        let frigate = EnemyTemplate {
+            name: "Frigate",
+            hull: 3,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![]),
+            art: None,
+        };
+        spawn_enemies(&mut game, &[frigate]);
+
+        let lines = render_lines(build_enemy_paragraph(&game, &Theme::default()), 30, 8);
+        assert_eq!(lines[4], format!("│{:28}│", "  <____ ?? ____>"));
+    }
+
+    #[test]
+    fn test_intent_shows_mitigated_damage() {
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 20);
+        s.insert(Attribute::Shields, 5);
+        let player = Player { name: String::from("Player"), state: s };
+        let template = EnemyTemplate {
+            name: "Frigate",
+            hull: 10,
+            shields: 0,
+            ai: EnemyAi::Cycle(vec![EnemyIntent::Attack(8)]),
+            art: None,
+        };
+        let mut game = GameStateBuilder::new(CardCollection::new(), vec![])
+            .player(player)
+            .enemies(&[template])
+            .build();
+
+        let enemy = game.entity(&game.enemy.unwrap()).unwrap();
+        let intent = enemy.peek_intent().unwrap();
+        let player_state = game.entity(&game.player).unwrap().get_state();
+        assert_eq!(format_intent(intent, player_state), "Attack 8 → 3 through");
+
+        // The hull loses what the intent showed
+        let shown = mitigated_damage(player_state, 8);
+        game.actions.push_back(Action::EndTurn);
+        tick(&mut game);
+        assert_eq!(game.attribute(&game.player, Attribute::Hull), 20 - shown);
+    }
+
+    #[test]
+    fn test_turn_order_strip() {
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 20);
+        let player = Player { name: String::from("Player"), state: s };
+        let template = |name: &'static str, ai: EnemyAi| EnemyTemplate {
+            name,
+            hull: 10,
+            shields: 0,
+            ai,
+            art: None,
+        };
+        let game = GameStateBuilder::new(CardCollection::new(), vec![])
+            .player(player)
+            .enemies(&[
+                template("Frigate", EnemyAi::Cycle(vec![EnemyIntent::Attack(4)])),
+                template("Drone", EnemyAi::Weighted(vec![(EnemyIntent::Defend(2), 1)])),
+            ])
+            .build();
+
+        let lines = render_lines(build_turn_order(&game, &Theme::default()), 60, 3);
+        assert_eq!(
+            lines[1],
+            format!("│{:58}│", "1. Frigate: Attack 4 → 4 through  /  2. Drone: ?")
+        );
+    }
+
+    #[test]
+    fn test_forecast_lines() {
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 20);
+        s.insert(Attribute::Shields, 1);
+        let player = Player { name: String::from("Player"), state: s };
+        let template = |name: &'static str, ai: EnemyAi| EnemyTemplate {
+            name,
+            hull: 10,
+            shields: 0,
+            ai,
+            art: None,
+        };
+        let intents = vec![EnemyIntent::Attack(4), EnemyIntent::Defend(2)];
+        let game = GameStateBuilder::new(CardCollection::new(), vec![])
+            .player(player)
+            .enemies(&[
+                template("Frigate", EnemyAi::Cycle(intents)),
+                template("Hulk", EnemyAi::Cycle(vec![])),
+            ])
+            .build();
+
+        let text: Vec<String> = forecast_lines(&game, &Theme::default())
+            .iter()
+            .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+            .collect();
+        assert_eq!(text[0], "Frigate: Attack 4 → 3 through, then Defend 2");
+        assert_eq!(text[1], "Hulk: Nothing planned");
+    }
+
+    #[test]
+    fn test_format_state() {
+        let mut s = State::new();
+        s.insert(Attribute::DeflectorField, 2);
+        s.insert(Attribute::Hull, 7);
+        s.insert(Attribute::Shields, 3);
+
+        assert_eq!(format_state(&s), "Shields: 3  /  Hull: 7  /  Deflector Field: 2");
+        assert_eq!(format_state(&State::new()), "");
+
+        // Max hull is shown alongside the hull
+        s.insert(Attribute::MaxHull, 10);
+        assert_eq!(format_state(&s), "Shields: 3  /  Hull: 7/10  /  Deflector Field: 2");
+
+        s.insert(Attribute::MaxShields, 5);
+        assert_eq!(format_state(&s), "Shields: 3/5  /  Hull: 7/10  /  Deflector Field: 2");
+    }
+
+    #[test]
+    fn test_is_critical() {
+        let state = |hull: i32| {
+            let mut s = State::new();
+            s.insert(Attribute::Hull, hull);
+            s.insert(Attribute::MaxHull, 20);
+            s
+        };
+
+        // 25% of the max hull is the threshold
+        assert!(!is_critical(&state(6)));
+        assert!(!is_critical(&state(5)));
+        assert!(is_critical(&state(4)));
+        assert!(is_critical(&state(0)));
+
+        // Without a max hull there's nothing to compare against
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 1);
+        assert!(!is_critical(&s));
+    }
+
+    #[test]
+    fn test_render_modal() {
+        let mut terminal = Terminal::new(TestBackend::new(50, 15)).unwrap();
+        terminal
+            .draw(|f| {
+                let lines = vec![Spans::from("Hello"), Spans::from("World")];
+                render_modal(f, "Greeting", lines, &Theme::default());
+            })
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let lines: Vec<String> = buffer
+            .content()
+            .chunks(50)
+            .map(|cells| cells.iter().map(|c| c.symbol.as_str()).collect())
+            .collect();
+
+        // The modal is in the middle third of the screen
+        assert_eq!(lines[4], format!("{:10}┌Greeting{}┐{:10}", "", "─".repeat(20), ""));
+        let text = |line: &str| line.trim().trim_matches('│').trim().to_string();
+        assert_eq!(text(&lines[5]), "Hello");
+        assert_eq!(text(&lines[6]), "World");
+        assert_eq!(lines[7], format!("{:10}└{}┘{:10}", "", "─".repeat(28), ""));
+    }
+
+    #[test]
+    fn test_format_preview() {
+        let mut before = State::new();
+        before.insert(Attribute::Hull, 5);
+        before.insert(Attribute::Shields, 10);
+        let mut after = before.clone();
+        after.insert(Attribute::Shields, 7);
+
+        assert_eq!(format_preview(&before, &after), "Shields: 10→7");
+    }
+
+    #[test]
+    fn test_pile_modal_lines() {
+        let mut cards = CardCollection::new();
+        for (id, name) in [(CardId::SHIELDS, "Shields"), (CardId::PHASERS, "Phasers")].iter() {
+            cards.insert(Card {
+                id: *id,
+                name,
+                effects: vec![],
+                target: Target::Player,
+                tags: vec![CardTag::Skill],
+                cost: 1,
+            });
+        }
+        let mut game = GameState::new(cards, vec![CardId::SHIELDS, CardId::PHASERS]);
+        game.discard = game.instances(&[CardId::SHIELDS, CardId::PHASERS]);
+
+        let text = |state: &ViewPile| -> Vec<String> {
+            pile_modal_lines(&game, state, &Theme::default())
+                .iter()
+                .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+                .collect()
+        };
+        let view = |pile| GuiStateMachine::<ViewPile>::new(pile).state;
+
+        // The draw pile order is hidden but the discard pile's isn't
+        assert_eq!(text(&view(PileKind::Draw))[..2], ["Draw pile (2)", "Phasers, Shields"]);
+        assert_eq!(
+            text(&view(PileKind::Discard))[..2],
+            ["Discard pile (2)", "Shields, Phasers"]
+        );
+        assert_eq!(text(&view(PileKind::Exhaust))[..2], ["Exhaust pile (0)", "Empty"]);
+
+        // Filtering shows how many of the pile's cards matched
+        let mut state = view(PileKind::Discard);
+        state.query = String::from("sh");
+        state.typing = true;
+        assert_eq!(
+            text(&state),
+            ["Discard pile (1 of 2)", "Shields", "Filter: sh_", "[Enter]Done [Esc]Clear"]
+        );
+    }
+
+    #[test]
+    fn test_prompt_text() {
+        let mut cards = CardCollection::new();
+        cards.insert(Card {
+            id: CardId::SHIELDS,
+            name: "Shields",
+            effects: vec![Box::new(IncreaseShields { amount: 1 })],
+            target: Target::Player,
+            tags: vec![CardTag::Skill],
+            cost: 1,
+        });
+        let mut game = GameState::new(cards, vec![]);
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 10);
+        let player = Player { name: String::from("Player"), state: s };
+        game.player = game.add_entity(None, Box::new(player));
+
+        assert_eq!(prompt_text(&game, Key::Char('e')), "No cards to play. Press [e] to end turn.");
+
+        game.hand = game.instances(&[CardId::SHIELDS]);
+        game.energy = 0;
+        assert_eq!(
+            prompt_text(&game, Key::Char('\n')),
+            "No playable cards. Press [Enter] to end turn."
+        );
+
+        game.energy = 1;
+        assert_eq!(prompt_text(&game, Key::Char('e')), "Select a card to play");
+    }
+
+    #[test]
+    fn test_format_clock() {
+        assert_eq!(format_clock(Duration::ZERO), "0:00.0");
+        assert_eq!(format_clock(Duration::from_millis(65_349)), "1:05.3");
+        assert_eq!(format_clock(Duration::from_secs(600)), "10:00.0");
+    }
+
+    #[test]
+    fn test_format_statuses() {
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 7);
+        s.insert(Attribute::Evasion, 10);
+        s.insert(Attribute::Strength, -1);
+        s.insert(Attribute::DeflectorField, 2);
+        s.insert(Attribute::Dexterity, 0);
+        let player = Player { name: String::from("Player"), state: s };
+
+        // Statuses follow the attribute order and empty ones are hidden
+        assert_eq!(format_statuses(&player), "⚡2 ⚔-1 ≈10");
+        assert_eq!(format_entity(&player), "Hull: 7  ⚡2 ⚔-1 ≈10");
+
+        let mut s = State::new();
+        s.insert(Attribute::Hull, 7);
+        let player = Player { name: String::from("Player"), state: s };
+        assert_eq!(format_statuses(&player), "");
+        assert_eq!(format_entity(&player), "Hull: 7");
+    }
+
+    #[test]
+    fn test_rest_back_key() {
+        let mut game = GameState::new(CardCollection::new(), vec![]);
+        game.master_deck = game.instances(&[CardId::PHASERS]);
+        let state = GuiStateMachine::<Rest>::new(RestChoice::Upgrade).state;
+
+        let lines = render_lines(build_rest(&game, &state, Key::Esc, &Theme::default()), 50, 10);
+        assert_eq!(lines[7], format!("│{:^48}│", "[Up/Down]Select [Enter]Confirm [Esc]Back"));
+    }
+
+    #[test]
+    fn test_game_over() {
+        let stats = RunStats::default();
+        let theme = Theme::default();
+
+        let game_over = build_game_over(Outcome::Conceded, &stats, Key::Esc, &theme);
+        let lines = render_lines(game_over, 40, 10);
+        assert_eq!(lines[1].trim_matches(|c| c == '│' || c == ' '), "You abandoned the fight");
+        assert_eq!(lines[8].trim_matches(|c| c == '│' || c == ' '), "[Esc]Quit");
+    }
+}
@@ -0,0 +1,104 @@
+//! Colors used to render the game. The default theme can be
+//! overridden by a TOML file where any missing keys fall back to the
+//! default e.g.
+//!
+//! ```toml
+//! ship = "LightBlue"
+//! border = "DarkGray"
+//! ```
+use std::{error::Error, fs, path::Path};
+
+use serde::Deserialize;
+use tui::style::{Color, Modifier, Style};
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Color of the enemy ship
+    pub ship: Color,
+    /// Color the enemy ship flashes when hit
+    pub flash: Color,
+    /// Color of the keys to press in prompts
+    pub prompt: Color,
+    /// Color of list contents like the hand and discard pile
+    pub text: Color,
+    /// Background color of modals
+    pub modal: Color,
+    /// Color of the status bar when the hull is critical
+    pub critical: Color,
+    /// Color of cards in the hand that can't be played
+    pub disabled: Color,
+    pub highlight: Option<Color>,
+    pub border: Option<Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            ship: Color::LightYellow,
+            flash: Color::LightRed,
+            prompt: Color::LightGreen,
+            text: Color::White,
+            modal: Color::Black,
+            critical: Color::Red,
+            disabled: Color::DarkGray,
+            highlight: None,
+            border: None,
+        }
+    }
+}
+
+impl Theme {
+    pub fn from_toml(s: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(s)
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = fs::read_to_string(path)?;
+        Ok(Self::from_toml(&contents)?)
+    }
+
+    pub fn highlight_style(&self) -> Style {
+        let style = Style::default().add_modifier(Modifier::ITALIC);
+        match self.highlight {
+            Some(color) => style.fg(color),
+            None => style,
+        }
+    }
+
+    pub fn border_style(&self) -> Style {
+        match self.border {
+            Some(color) => Style::default().fg(color),
+            None => Style::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_theme {
+    use super::*;
+
+    #[test]
+    fn test_loaded_theme_overrides_default() {
+        let theme = Theme::from_toml(
+            r#"
+            ship = "LightBlue"
+            border = "DarkGray"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(theme.ship, Color::LightBlue);
+        assert_eq!(theme.border_style(), Style::default().fg(Color::DarkGray));
+
+        // Anything not in the file is the default
+        let default = Theme::default();
+        assert_eq!(theme.prompt, default.prompt);
+        assert_eq!(theme.highlight_style(), default.highlight_style());
+    }
+
+    #[test]
+    fn test_invalid_theme() {
+        assert!(Theme::from_toml("ship = \"NotAColor\"").is_err());
+    }
+}